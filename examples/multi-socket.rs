@@ -1,6 +1,6 @@
 use prebuilt::{constraints::UnaryConstraint, processing::RandomObserver};
 use std::collections::BTreeSet;
-use wfc::{prebuilt::Dim3d, prelude::*, StateBuilder};
+use wfc::{io::vox::Voxels, prebuilt::Dim3d, prelude::*, StateBuilder};
 
 fn main() {
   #[cfg(feature = "profiling")]
@@ -23,4 +23,13 @@ fn main() {
   let mut state = builder.build().expect("Failed to build state");
 
   wfc::collapse(&mut state).expect("Failed to collapse");
+
+  // pass a path to view the output in MagicaVoxel, with the lowest variant left empty
+  if let Some(path) = std::env::args().nth(1) {
+    let voxels = Voxels::from_state(&state, |variant| {
+      (*variant > 0).then(|| [*variant as u8 * 80, 160, 255 - *variant as u8 * 80, 255])
+    })
+    .expect("Failed to export");
+    voxels.save(path).expect("Failed to save");
+  }
 }
@@ -1,18 +1,16 @@
 use maplit::hashmap;
 use prebuilt::{
+  Dim2d,
   auto::GenericFinder,
   constraints::UnaryConstraint,
   e2e::maze2d::{Maze2dTypeSet, MazeRuleProvider},
   processing::{LimitMod, WeightedObserver},
-  shapes::{InformedShape, MultiShape, WeightedShape},
-  Dim2d,
+  shapes::{InformedShape, MultiShape},
 };
-use std::{
-  collections::HashMap,
-  error::Error,
-  fmt::{Debug, Display},
+use std::{collections::HashMap, error::Error, fmt::Debug};
+use wfc::{
+  Constraint, Dimension, Modifier, Observer, Socket, Variant, io::text::TextGrid, prelude::*,
 };
-use wfc::{prelude::*, Constraint, Dimension, Modifier, Observer, Socket, Variant};
 
 const STEP_BY_STEP: bool = false;
 
@@ -45,30 +43,23 @@ fn main() -> Result<(), Box<dyn Error>> {
   #[cfg(feature = "profiling")]
   let _guards = wfc::perf::enable_profiling();
 
-  let rows = 7;
-  let cols = 10;
-  let source = "\
-  ╔════════╗\
-  ║...╔═╦═╗║\
-  ║.╔═╩═╣.║║\
-  E╦╣╔══╣.╠X\
-  ║║╚╝.╔╩═╝║\
-  ║╚═══╝...║\
-  ╚════════╝\
-  "
-  .chars()
-  .collect::<Vec<_>>();
-
-  assert_eq!(source.len(), rows * cols);
-
-  let finder = GenericFinder::new(
-    MazeRuleProvider::<char, TextMaze>::default(),
-    source,
-    [cols, rows],
-  );
+  let grid = TextGrid::chars("EX.║═╔╗╚╝╬╩╦╠╣".chars());
+
+  let source = grid.parse::<2>(
+    "\
+╔════════╗
+║...╔═╦═╗║
+║.╔═╩═╣.║║
+E╦╣╔══╣.╠X
+║║╚╝.╔╩═╝║
+║╚═══╝...║
+╚════════╝",
+  )?;
 
-  let rules = match finder.find() {
-    Ok(rules) => rules,
+  let finder = GenericFinder::from_samples(MazeRuleProvider::<char, TextMaze>::default(), [source]);
+
+  let (rules, frequencies) = match finder.find_with_frequencies() {
+    Ok(found) => found,
     Err(e) => {
       eprintln!("{e}");
       return Ok(());
@@ -79,13 +70,8 @@ fn main() -> Result<(), Box<dyn Error>> {
 
   let seed: Option<u64> = args.get(1).map(|arg| arg.parse()).transpose().unwrap();
 
-  let weights = rules
-    .variants()
-    .map(|k| (*k, 1))
-    .collect::<HashMap<char, usize>>();
-
   let shape = MultiShape::new(
-    WeightedShape::new(weights),
+    frequencies.weights(),
     InformedShape::new(INFLUENCE_RADIUS, 1, HashMap::default()),
   );
 
@@ -112,19 +98,21 @@ fn main() -> Result<(), Box<dyn Error>> {
   let state = builder.build()?;
 
   if STEP_BY_STEP {
-    step_by_step(state);
+    step_by_step(state, &grid);
   } else {
-    all_at_once(state);
+    all_at_once(state, &grid);
   }
 
   Ok(())
 }
 
-fn all_at_once<O, C, V, D, S, const DIM: usize>(mut state: State<O, C, V, D, S, DIM>)
-where
+fn all_at_once<O, C, V, D, S, const DIM: usize>(
+  mut state: State<O, C, V, D, S, DIM>,
+  grid: &TextGrid<V>,
+) where
   O: Observer<V>,
   C: Constraint<S>,
-  V: Variant + Display,
+  V: Variant,
   D: Dimension,
   S: Socket,
 {
@@ -133,14 +121,16 @@ where
     return;
   }
 
-  print_state(state);
+  print_state(state, grid);
 }
 
-fn step_by_step<O, C, V, D, S, const DIM: usize>(mut state: State<O, C, V, D, S, DIM>)
-where
+fn step_by_step<O, C, V, D, S, const DIM: usize>(
+  mut state: State<O, C, V, D, S, DIM>,
+  grid: &TextGrid<V>,
+) where
   O: Observer<V>,
   C: Constraint<S>,
-  V: Variant + Display + Default,
+  V: Variant,
   D: Dimension,
   S: Socket,
 {
@@ -148,18 +138,7 @@ where
     match state.collapse() {
       Ok(Observation::Incomplete(_)) => {
         println!("\n");
-
-        let data: Vec<_> = state.data();
-
-        let output = itertools::join(
-          (0..ROWS).map(|i| {
-            let slice = &data[i * COLS..i * COLS + COLS];
-            itertools::join(slice, "")
-          }),
-          "\n",
-        );
-
-        println!("{output}");
+        println!("{}", grid.render_state(&state).unwrap());
       }
       Err(err) => {
         eprintln!("{err}");
@@ -171,38 +150,24 @@ where
     }
   }
 
-  print_state(state);
+  print_state(state, grid);
 }
 
-fn print_state<O, C, V, D, S, const DIM: usize>(state: State<O, C, V, D, S, DIM>)
-where
+fn print_state<O, C, V, D, S, const DIM: usize>(
+  state: State<O, C, V, D, S, DIM>,
+  grid: &TextGrid<V>,
+) where
   O: Observer<V>,
   C: Constraint<S>,
-  V: Variant + Display,
+  V: Variant,
   D: Dimension,
   S: Socket,
 {
+  let size = *state.size();
   let data: Vec<_> = state.into();
 
-  let output = itertools::join(
-    (0..ROWS).map(|i| {
-      let slice = &data[i * COLS..i * COLS + COLS];
-      itertools::join(slice, "")
-    }),
-    "\n",
-  );
-
-  println!("\n{output}\n");
-
-  let mut rows = (0..ROWS)
-    .map(|i| {
-      let slice = &data[i * COLS..i * COLS + COLS];
-      itertools::join(slice, "")
-    })
-    .collect::<Vec<_>>();
-
-  rows.reverse();
+  println!("\n{}\n", grid.render(&data, size).unwrap());
 
-  let output = itertools::join(rows, "\n");
-  println!("{output}");
+  let flipped = grid.clone().with_flip_y(true);
+  println!("{}", flipped.render(&data, size).unwrap());
 }
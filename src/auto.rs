@@ -28,12 +28,24 @@ pub trait SocketProvider<V, D, S> {
 #[derive(Default, Debug)]
 pub struct NoSocket;
 
+/// Describes where in a sample a socket could not be found
+#[derive(Debug)]
+pub struct SocketFailure<V, D> {
+  /// Index of the sample the failure occurred in
+  pub sample: usize,
+  /// Position of the source variant within the sample
+  pub position: Vec<usize>,
+  pub dir: D,
+  pub source: V,
+  pub target: V,
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error<V, D> {
   #[error("The provided dimensions could not be used")]
   DimensionTooLarge,
   #[error("Failed to find rules for: {0:#?}")]
-  RuleNotFound(Vec<(D, V, V)>),
+  RuleNotFound(Vec<SocketFailure<V, D>>),
   #[error("Failed to generate socket {0:?} => {1:?} => {2:?}")]
   SocketGenerationFailure(V, D, V),
 }
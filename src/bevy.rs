@@ -0,0 +1,459 @@
+//! A Bevy plugin that runs generations on the async compute task pool.
+//!
+//! Spawn a [`Generator`] holding a [`StateBuilder`] and the plugin builds and collapses it in the background,
+//! collapsing for at most the generator's budget before yielding back to the pool. Progress, completion and
+//! contradictions are reported through messages, and the finished [`State`] is kept on the generator.
+//! Generators can also take their rules from an asset, see [`asset`]
+
+pub mod asset;
+pub mod spawn;
+
+use crate::{
+  Dimension, DirectionalConstraint, Observation, Observer, Rules, Socket, State, StateBuilder,
+  Variant, err::Error,
+};
+use asset::GeneratorRules;
+use bevy_app::{App, Plugin, Update};
+use bevy_asset::{AssetEvent, Assets};
+use bevy_ecs::prelude::*;
+use bevy_platform::time::Instant;
+use bevy_reflect::TypePath;
+use bevy_tasks::{AsyncComputeTaskPool, Task, block_on, futures_lite::future, poll_once};
+use std::{
+  collections::HashSet,
+  marker::PhantomData,
+  sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+  },
+  time::Duration,
+};
+
+/// Runs every [`Generator`] with matching types
+pub struct WfcPlugin<O, C, V, D, S, const DIM: usize>(PhantomData<(O, C, V, D, S)>);
+
+impl<O, C, V, D, S, const DIM: usize> Default for WfcPlugin<O, C, V, D, S, DIM> {
+  fn default() -> Self {
+    Self(PhantomData)
+  }
+}
+
+impl<O, C, V, D, S, const DIM: usize> Plugin for WfcPlugin<O, C, V, D, S, DIM>
+where
+  O: Observer<V> + Clone + Send + Sync + 'static,
+  C: DirectionalConstraint<V, D, S> + Clone + Send + Sync + 'static,
+  V: Variant + TypePath + Send + Sync + 'static,
+  D: Dimension + TypePath + Send + Sync + 'static,
+  S: Socket + TypePath + Send + Sync + 'static,
+{
+  fn build(&self, app: &mut App) {
+    app
+      .add_message::<GenerationProgress>()
+      .add_message::<GenerationComplete>()
+      .add_message::<GenerationContradiction<DIM>>()
+      .add_systems(
+        Update,
+        (
+          reload_rules::<O, C, V, D, S, DIM>
+            .run_if(resource_exists::<Messages<AssetEvent<Rules<V, D, S>>>>),
+          start_generation::<O, C, V, D, S, DIM>,
+          poll_generation::<O, C, V, D, S, DIM>,
+        )
+          .chain(),
+      );
+  }
+}
+
+type GenerationTask<O, C, V, D, S, const DIM: usize> =
+  Task<Result<State<O, C, V, D, S, DIM>, Error<DIM>>>;
+
+type Generators<'w, 's, O, C, V, D, S, const DIM: usize> =
+  Query<'w, 's, (Entity, &'static mut Generator<O, C, V, D, S, DIM>)>;
+
+type RuledGenerators<'w, 's, O, C, V, D, S, const DIM: usize> = Query<
+  'w,
+  's,
+  (
+    &'static mut Generator<O, C, V, D, S, DIM>,
+    &'static GeneratorRules<V, D, S>,
+  ),
+>;
+
+type MaybeRuledGenerators<'w, 's, O, C, V, D, S, const DIM: usize> = Query<
+  'w,
+  's,
+  (
+    &'static mut Generator<O, C, V, D, S, DIM>,
+    Option<&'static GeneratorRules<V, D, S>>,
+  ),
+>;
+
+/// Generates a state from the builder in the background
+#[derive(Component)]
+pub struct Generator<O, C, V, D, S, const DIM: usize>
+where
+  O: Observer<V> + Clone + Send + Sync + 'static,
+  C: DirectionalConstraint<V, D, S> + Clone + Send + Sync + 'static,
+  V: Variant + TypePath + Send + Sync + 'static,
+  D: Dimension + TypePath + Send + Sync + 'static,
+  S: Socket + TypePath + Send + Sync + 'static,
+{
+  source: StateBuilder<O, C, V, D, S, DIM>,
+  pending: bool,
+  budget: Duration,
+  task: Option<GenerationTask<O, C, V, D, S, DIM>>,
+  collapsed: Arc<AtomicUsize>,
+  reported: usize,
+  total: usize,
+  state: Option<State<O, C, V, D, S, DIM>>,
+}
+
+impl<O, C, V, D, S, const DIM: usize> Generator<O, C, V, D, S, DIM>
+where
+  O: Observer<V> + Clone + Send + Sync + 'static,
+  C: DirectionalConstraint<V, D, S> + Clone + Send + Sync + 'static,
+  V: Variant + TypePath + Send + Sync + 'static,
+  D: Dimension + TypePath + Send + Sync + 'static,
+  S: Socket + TypePath + Send + Sync + 'static,
+{
+  pub fn new(builder: StateBuilder<O, C, V, D, S, DIM>) -> Self {
+    Self {
+      source: builder,
+      pending: true,
+      budget: Duration::from_millis(4),
+      task: None,
+      collapsed: Default::default(),
+      reported: 0,
+      total: 0,
+      state: None,
+    }
+  }
+
+  /// How long to collapse for before yielding back to the task pool
+  pub fn with_budget(mut self, budget: Duration) -> Self {
+    self.budget = budget;
+    self
+  }
+
+  /// Cancels any running generation and starts again with the builder
+  pub fn restart(&mut self, builder: StateBuilder<O, C, V, D, S, DIM>) {
+    self.source = builder;
+    self.rerun();
+  }
+
+  /// Cancels any running generation and starts again from the same builder
+  pub fn rerun(&mut self) {
+    self.task = None;
+    self.state = None;
+    self.pending = true;
+  }
+
+  /// Cancels any running generation and starts again with different rules
+  pub fn set_rules(&mut self, rules: impl Into<Rules<V, D, S>>) {
+    self.source.set_rules(rules);
+    self.rerun();
+  }
+
+  pub fn builder(&self) -> &StateBuilder<O, C, V, D, S, DIM> {
+    &self.source
+  }
+
+  pub fn is_running(&self) -> bool {
+    self.task.is_some() || self.pending
+  }
+
+  /// The finished state, once generation has completed
+  pub fn state(&self) -> Option<&State<O, C, V, D, S, DIM>> {
+    self.state.as_ref()
+  }
+
+  pub fn take_state(&mut self) -> Option<State<O, C, V, D, S, DIM>> {
+    self.state.take()
+  }
+
+  fn spawn(&mut self) {
+    let builder = self.source.clone();
+    let budget = self.budget;
+    self.pending = false;
+    let collapsed = Arc::new(AtomicUsize::new(0));
+    self.collapsed = collapsed.clone();
+    self.reported = 0;
+    self.total = builder.size().len();
+
+    self.task = Some(AsyncComputeTaskPool::get().spawn(async move {
+      let mut state = builder.build()?;
+      // only observations collapse cells, so counting them keeps track of the progress
+      let mut done = state.size().len() - state.cells().uncollapsed_count();
+
+      loop {
+        let start = Instant::now();
+
+        while start.elapsed() < budget {
+          if state.collapse()? == Observation::Complete {
+            return Ok(state);
+          }
+          done += 1;
+        }
+
+        collapsed.store(done, Ordering::Relaxed);
+
+        future::yield_now().await;
+      }
+    }));
+  }
+}
+
+/// Sent whenever more cells of a generator have collapsed
+#[derive(Message, Debug, Clone)]
+pub struct GenerationProgress {
+  pub entity: Entity,
+  pub collapsed: usize,
+  pub total: usize,
+}
+
+/// Sent once the state of a generator is ready
+#[derive(Message, Debug, Clone)]
+pub struct GenerationComplete {
+  pub entity: Entity,
+}
+
+/// Sent when a generator fails to build or collapse
+#[derive(Message, Debug)]
+pub struct GenerationContradiction<const DIM: usize> {
+  pub entity: Entity,
+  pub error: Error<DIM>,
+}
+
+/// Reruns generators whose rules asset has changed
+fn reload_rules<O, C, V, D, S, const DIM: usize>(
+  mut events: MessageReader<AssetEvent<Rules<V, D, S>>>,
+  mut generators: RuledGenerators<O, C, V, D, S, DIM>,
+) where
+  O: Observer<V> + Clone + Send + Sync + 'static,
+  C: DirectionalConstraint<V, D, S> + Clone + Send + Sync + 'static,
+  V: Variant + TypePath + Send + Sync + 'static,
+  D: Dimension + TypePath + Send + Sync + 'static,
+  S: Socket + TypePath + Send + Sync + 'static,
+{
+  let modified = events
+    .read()
+    .filter_map(|event| match event {
+      AssetEvent::Modified { id } => Some(*id),
+      _ => None,
+    })
+    .collect::<HashSet<_>>();
+
+  for (mut generator, rules) in &mut generators {
+    if modified.contains(&rules.0.id()) {
+      generator.rerun();
+    }
+  }
+}
+
+fn start_generation<O, C, V, D, S, const DIM: usize>(
+  mut generators: MaybeRuledGenerators<O, C, V, D, S, DIM>,
+  rules: Option<Res<Assets<Rules<V, D, S>>>>,
+) where
+  O: Observer<V> + Clone + Send + Sync + 'static,
+  C: DirectionalConstraint<V, D, S> + Clone + Send + Sync + 'static,
+  V: Variant + TypePath + Send + Sync + 'static,
+  D: Dimension + TypePath + Send + Sync + 'static,
+  S: Socket + TypePath + Send + Sync + 'static,
+{
+  for (mut generator, handle) in &mut generators {
+    if !generator.pending {
+      continue;
+    }
+
+    // generators using a rules asset wait for it to load, and always use its latest version
+    if let Some(handle) = handle {
+      match rules.as_ref().and_then(|rules| rules.get(&handle.0)) {
+        Some(rules) => {
+          generator.source.set_rules(rules.clone());
+        }
+        None => continue,
+      }
+    }
+
+    generator.spawn();
+  }
+}
+
+fn poll_generation<O, C, V, D, S, const DIM: usize>(
+  mut generators: Generators<O, C, V, D, S, DIM>,
+  mut progress: MessageWriter<GenerationProgress>,
+  mut complete: MessageWriter<GenerationComplete>,
+  mut contradiction: MessageWriter<GenerationContradiction<DIM>>,
+) where
+  O: Observer<V> + Clone + Send + Sync + 'static,
+  C: DirectionalConstraint<V, D, S> + Clone + Send + Sync + 'static,
+  V: Variant + TypePath + Send + Sync + 'static,
+  D: Dimension + TypePath + Send + Sync + 'static,
+  S: Socket + TypePath + Send + Sync + 'static,
+{
+  for (entity, mut generator) in &mut generators {
+    let Some(task) = generator.task.as_mut() else {
+      continue;
+    };
+
+    let result = block_on(poll_once(task));
+
+    let collapsed = match &result {
+      Some(Ok(_)) => generator.total,
+      _ => generator.collapsed.load(Ordering::Relaxed),
+    };
+
+    if collapsed > generator.reported {
+      generator.reported = collapsed;
+      progress.write(GenerationProgress {
+        entity,
+        collapsed,
+        total: generator.total,
+      });
+    }
+
+    match result {
+      Some(Ok(state)) => {
+        generator.task = None;
+        generator.state = Some(state);
+        complete.write(GenerationComplete { entity });
+      }
+      Some(Err(error)) => {
+        generator.task = None;
+        contradiction.write(GenerationContradiction { entity, error });
+      }
+      None => {}
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{
+    GenerationComplete, GenerationContradiction, GenerationProgress, Generator, WfcPlugin,
+  };
+  use crate::{
+    RuleBuilder, Rules, StateBuilder,
+    prebuilt::{constraints::UnaryConstraint, dims::bevy::Dim2d, processing::RandomObserver},
+  };
+  use bevy_app::{App, TaskPoolPlugin, Update};
+  use bevy_ecs::prelude::*;
+  use std::time::Duration;
+
+  type TestGenerator = Generator<RandomObserver, UnaryConstraint, u8, Dim2d, u8, 2>;
+  type TestPlugin = WfcPlugin<RandomObserver, UnaryConstraint, u8, Dim2d, u8, 2>;
+
+  #[derive(Resource, Default)]
+  struct Received {
+    progress: usize,
+    complete: Vec<Entity>,
+    contradictions: Vec<Entity>,
+  }
+
+  fn record(
+    mut received: ResMut<Received>,
+    mut progress: MessageReader<GenerationProgress>,
+    mut complete: MessageReader<GenerationComplete>,
+    mut contradiction: MessageReader<GenerationContradiction<2>>,
+  ) {
+    received.progress += progress.read().count();
+    received
+      .complete
+      .extend(complete.read().map(|message| message.entity));
+    received
+      .contradictions
+      .extend(contradiction.read().map(|message| message.entity));
+  }
+
+  fn app() -> App {
+    let mut app = App::new();
+    app
+      .add_plugins((TaskPoolPlugin::default(), TestPlugin::default()))
+      .init_resource::<Received>()
+      .add_systems(
+        Update,
+        record.after(super::poll_generation::<RandomObserver, UnaryConstraint, u8, Dim2d, u8, 2>),
+      );
+    app
+  }
+
+  fn run(app: &mut App) {
+    for _ in 0..1000 {
+      app.update();
+      let received = app.world().resource::<Received>();
+      if !received.complete.is_empty() || !received.contradictions.is_empty() {
+        return;
+      }
+      std::thread::sleep(Duration::from_millis(1));
+    }
+  }
+
+  #[test]
+  fn generations_complete_in_the_background() {
+    let rules: Rules<u8, Dim2d, u8> = RuleBuilder::default()
+      .with_rule(0, |_| 0)
+      .with_rule(1, |_| 0)
+      .into();
+
+    let mut app = app();
+    let entity = app
+      .world_mut()
+      .spawn(
+        TestGenerator::new(StateBuilder::new(
+          [30, 30],
+          RandomObserver::new(Some(3)),
+          UnaryConstraint,
+          rules,
+        ))
+        .with_budget(Duration::from_micros(50)),
+      )
+      .id();
+
+    run(&mut app);
+
+    let received = app.world().resource::<Received>();
+    assert_eq!(received.complete, vec![entity]);
+    assert!(received.progress > 0);
+
+    let generator = app.world().get::<TestGenerator>(entity).unwrap();
+    assert!(!generator.is_running());
+    assert_eq!(
+      generator
+        .state()
+        .unwrap()
+        .data_raw()
+        .iter()
+        .flatten()
+        .count(),
+      900
+    );
+  }
+
+  #[test]
+  fn contradictions_are_reported() {
+    let rules: Rules<u8, Dim2d, u8> = RuleBuilder::default()
+      .with_rule(0, |_| 0)
+      .with_rule(1, |_| 1)
+      .into();
+
+    let mut builder =
+      StateBuilder::new([4, 4], RandomObserver::new(Some(3)), UnaryConstraint, rules);
+    builder.insert([0, 0], 0).insert([1, 0], 1);
+
+    let mut app = app();
+    let entity = app.world_mut().spawn(TestGenerator::new(builder)).id();
+
+    run(&mut app);
+
+    let received = app.world().resource::<Received>();
+    assert_eq!(received.contradictions, vec![entity]);
+    assert!(
+      app
+        .world()
+        .get::<TestGenerator>(entity)
+        .unwrap()
+        .state()
+        .is_none()
+    );
+  }
+}
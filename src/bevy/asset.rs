@@ -0,0 +1,417 @@
+//! [`Rules`] as Bevy assets, loaded from tile set files.
+//!
+//! Tile sets are read from `.tileset.ron`, `.tileset.json` or `.tileset.toml` files, depending on which of the
+//! `ron`, `json` and `toml` features are enabled. The loaded [`Rules`] are the main asset, while the whole
+//! [`TileSet`] is available under the `tileset` label for its weights, limits and connections.
+//! Tile sets with symmetric tiles need [`RulesPlugin::expanded`] to name the extra orientations.
+//!
+//! A [`Generator`](super::Generator) with [`GeneratorRules`] waits for the rules to load and runs again whenever
+//! they change, so editing the file with Bevy's `file_watcher` enabled regenerates everything using it
+
+#[cfg(any(feature = "ron", feature = "json", feature = "toml"))]
+use crate::Planar;
+#[cfg(any(feature = "ron", feature = "json", feature = "toml"))]
+use crate::io::tileset;
+#[cfg(feature = "serde")]
+use crate::io::tileset::TileSet;
+use crate::{Dimension, Rules, Socket, Variant};
+use bevy_app::{App, Plugin};
+use bevy_asset::prelude::*;
+use bevy_asset::{UntypedAssetId, VisitAssetDependencies};
+use bevy_ecs::prelude::*;
+use bevy_reflect::TypePath;
+use std::marker::PhantomData;
+
+impl<V, D, S> VisitAssetDependencies for Rules<V, D, S>
+where
+  V: Variant,
+  D: Dimension,
+  S: Socket,
+{
+  fn visit_dependencies(&self, _visit: &mut impl FnMut(UntypedAssetId)) {}
+}
+
+impl<V, D, S> Asset for Rules<V, D, S>
+where
+  V: Variant + TypePath + Send + Sync,
+  D: Dimension + TypePath + Send + Sync,
+  S: Socket + TypePath + Send + Sync,
+{
+}
+
+#[cfg(feature = "serde")]
+impl<V, D, S, W> VisitAssetDependencies for TileSet<V, D, S, W>
+where
+  V: Variant,
+  D: Dimension,
+  S: Socket,
+{
+  fn visit_dependencies(&self, _visit: &mut impl FnMut(UntypedAssetId)) {}
+}
+
+#[cfg(feature = "serde")]
+impl<V, D, S, W> Asset for TileSet<V, D, S, W>
+where
+  V: Variant + TypePath + Send + Sync,
+  D: Dimension + TypePath + Send + Sync,
+  S: Socket + TypePath + Send + Sync,
+  W: TypePath + Send + Sync,
+{
+}
+
+/// Registers [`Rules`] as an asset, along with the tile set loader when any tile set format is enabled
+pub struct RulesPlugin<V: Variant, D: Dimension, S: Socket> {
+  #[cfg(any(feature = "ron", feature = "json", feature = "toml"))]
+  expansion: Option<Expansion<V, D, S>>,
+  marker: PhantomData<(V, D, S)>,
+}
+
+impl<V: Variant, D: Dimension, S: Socket> Default for RulesPlugin<V, D, S> {
+  fn default() -> Self {
+    Self {
+      #[cfg(any(feature = "ron", feature = "json", feature = "toml"))]
+      expansion: None,
+      marker: PhantomData,
+    }
+  }
+}
+
+#[cfg(any(feature = "ron", feature = "json", feature = "toml"))]
+impl<V, D, S> RulesPlugin<V, D, S>
+where
+  V: Variant,
+  D: Planar,
+  S: Socket,
+{
+  /// Expands symmetric tiles of loaded tile sets, naming each orientation after the first, see [`TileSet::expanded`].
+  /// The CLI names them `|variant, orientation| format!("{variant}@{orientation}")`
+  pub fn expanded(name: OrientationName<V>) -> Self {
+    Self {
+      expansion: Some(Expansion::new(name)),
+      marker: PhantomData,
+    }
+  }
+}
+
+#[cfg(not(any(feature = "ron", feature = "json", feature = "toml")))]
+impl<V, D, S> Plugin for RulesPlugin<V, D, S>
+where
+  V: Variant + TypePath + Send + Sync + 'static,
+  D: Dimension + TypePath + Send + Sync + 'static,
+  S: Socket + TypePath + Send + Sync + 'static,
+{
+  fn build(&self, app: &mut App) {
+    app.init_asset::<Rules<V, D, S>>();
+  }
+}
+
+#[cfg(any(feature = "ron", feature = "json", feature = "toml"))]
+impl<V, D, S> Plugin for RulesPlugin<V, D, S>
+where
+  V: Variant + TypePath + Send + Sync + serde::Serialize + serde::de::DeserializeOwned + 'static,
+  D: Dimension + TypePath + Send + Sync + serde::Serialize + serde::de::DeserializeOwned + 'static,
+  S: Socket + TypePath + Send + Sync + serde::Serialize + serde::de::DeserializeOwned + 'static,
+{
+  fn build(&self, app: &mut App) {
+    app
+      .init_asset::<Rules<V, D, S>>()
+      .init_asset::<TileSet<V, D, S>>()
+      .register_asset_loader(TileSetLoader::<V, D, S> {
+        expansion: self.expansion,
+        marker: PhantomData,
+      });
+  }
+}
+
+/// Uses the rules asset for the generator on the same entity
+#[derive(Component, Debug)]
+pub struct GeneratorRules<V, D, S>(pub Handle<Rules<V, D, S>>)
+where
+  V: Variant + TypePath + Send + Sync,
+  D: Dimension + TypePath + Send + Sync,
+  S: Socket + TypePath + Send + Sync;
+
+#[cfg(any(feature = "ron", feature = "json", feature = "toml"))]
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+  #[error(transparent)]
+  Io(#[from] std::io::Error),
+  #[error(transparent)]
+  Utf8(#[from] std::string::FromUtf8Error),
+  #[error(transparent)]
+  TileSet(#[from] tileset::Error),
+  #[error("No enabled tile set format for {0}")]
+  UnsupportedFormat(String),
+}
+
+/// Names an orientation of a symmetric tile, see [`TileSet::expanded`]
+#[cfg(any(feature = "ron", feature = "json", feature = "toml"))]
+type OrientationName<V> = fn(&V, usize) -> V;
+
+#[cfg(any(feature = "ron", feature = "json", feature = "toml"))]
+type Expand<V, D, S> = fn(&TileSet<V, D, S>, OrientationName<V>) -> TileSet<V, D, S>;
+
+/// Names the orientations of symmetric tiles, along with the expansion that is only available for planar dimensions
+#[cfg(any(feature = "ron", feature = "json", feature = "toml"))]
+struct Expansion<V: Variant, D: Dimension, S: Socket> {
+  name: OrientationName<V>,
+  expand: Expand<V, D, S>,
+}
+
+#[cfg(any(feature = "ron", feature = "json", feature = "toml"))]
+impl<V: Variant, D: Dimension, S: Socket> Clone for Expansion<V, D, S> {
+  fn clone(&self) -> Self {
+    *self
+  }
+}
+
+#[cfg(any(feature = "ron", feature = "json", feature = "toml"))]
+impl<V: Variant, D: Dimension, S: Socket> Copy for Expansion<V, D, S> {}
+
+#[cfg(any(feature = "ron", feature = "json", feature = "toml"))]
+impl<V: Variant, D: Planar, S: Socket> Expansion<V, D, S> {
+  fn new(name: OrientationName<V>) -> Self {
+    Self {
+      name,
+      expand: |tiles, name| tiles.expanded(name),
+    }
+  }
+}
+
+/// Loads a [`TileSet`] into [`Rules`]
+#[cfg(any(feature = "ron", feature = "json", feature = "toml"))]
+#[derive(TypePath)]
+pub struct TileSetLoader<V: Variant, D: Dimension, S: Socket> {
+  expansion: Option<Expansion<V, D, S>>,
+  marker: PhantomData<(V, D, S)>,
+}
+
+#[cfg(any(feature = "ron", feature = "json", feature = "toml"))]
+impl<V: Variant, D: Dimension, S: Socket> Default for TileSetLoader<V, D, S> {
+  fn default() -> Self {
+    Self {
+      expansion: None,
+      marker: PhantomData,
+    }
+  }
+}
+
+#[cfg(any(feature = "ron", feature = "json", feature = "toml"))]
+impl<V: Variant, D: Planar, S: Socket> TileSetLoader<V, D, S> {
+  /// Expands symmetric tiles before building the rules, see [`RulesPlugin::expanded`]
+  pub fn expanded(name: OrientationName<V>) -> Self {
+    Self {
+      expansion: Some(Expansion::new(name)),
+      marker: PhantomData,
+    }
+  }
+}
+
+#[cfg(any(feature = "ron", feature = "json", feature = "toml"))]
+impl<V, D, S> bevy_asset::AssetLoader for TileSetLoader<V, D, S>
+where
+  V: Variant + TypePath + Send + Sync + serde::Serialize + serde::de::DeserializeOwned + 'static,
+  D: Dimension + TypePath + Send + Sync + serde::Serialize + serde::de::DeserializeOwned + 'static,
+  S: Socket + TypePath + Send + Sync + serde::Serialize + serde::de::DeserializeOwned + 'static,
+{
+  type Asset = Rules<V, D, S>;
+  type Settings = ();
+  type Error = Error;
+
+  async fn load(
+    &self,
+    reader: &mut dyn bevy_asset::io::Reader,
+    _settings: &(),
+    load_context: &mut bevy_asset::LoadContext<'_>,
+  ) -> Result<Self::Asset, Self::Error> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes).await?;
+    let text = String::from_utf8(bytes)?;
+
+    let path = load_context.path().path().to_string_lossy().into_owned();
+    let tiles: TileSet<V, D, S> = match path.rsplit('.').next() {
+      #[cfg(feature = "ron")]
+      Some("ron") => TileSet::from_ron(&text)?,
+      #[cfg(feature = "json")]
+      Some("json") => TileSet::from_json(&text)?,
+      #[cfg(feature = "toml")]
+      Some("toml") => TileSet::from_toml(&text)?,
+      _ => return Err(Error::UnsupportedFormat(path)),
+    };
+
+    let tiles = match self.expansion {
+      Some(Expansion { name, expand }) => expand(&tiles, name),
+      None => tiles,
+    };
+
+    let rules = tiles.rules()?;
+    load_context.add_labeled_asset("tileset".into(), tiles);
+    Ok(rules)
+  }
+
+  fn extensions(&self) -> &[&str] {
+    &["tileset.ron", "tileset.json", "tileset.toml"]
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{GeneratorRules, RulesPlugin};
+  use crate::{
+    RuleBuilder, Rules, StateBuilder,
+    bevy::{Generator, WfcPlugin},
+    prebuilt::{constraints::UnaryConstraint, dims::bevy::Dim2d, processing::RandomObserver},
+  };
+  use bevy_app::{App, TaskPoolPlugin};
+  use bevy_asset::{AssetMetaCheck, AssetPlugin, prelude::*};
+  use std::time::Duration;
+
+  type TestRules = Rules<String, Dim2d, String>;
+  type TestGenerator = Generator<RandomObserver, UnaryConstraint, String, Dim2d, String, 2>;
+  type TestPlugin = WfcPlugin<RandomObserver, UnaryConstraint, String, Dim2d, String, 2>;
+
+  fn app(file_path: String) -> App {
+    app_with(file_path, RulesPlugin::default())
+  }
+
+  fn app_with(file_path: String, rules: RulesPlugin<String, Dim2d, String>) -> App {
+    let mut app = App::new();
+    app.add_plugins((
+      TaskPoolPlugin::default(),
+      AssetPlugin {
+        file_path,
+        meta_check: AssetMetaCheck::Never,
+        ..Default::default()
+      },
+      rules,
+      TestPlugin::default(),
+    ));
+    app
+  }
+
+  fn rules(variants: &[&str]) -> TestRules {
+    variants
+      .iter()
+      .fold(RuleBuilder::default(), |builder, variant| {
+        builder.with_rule(variant.to_string(), |_| "x".to_string())
+      })
+      .into()
+  }
+
+  fn generator() -> TestGenerator {
+    TestGenerator::new(StateBuilder::new(
+      [6, 6],
+      RandomObserver::new(Some(1)),
+      UnaryConstraint,
+      rules(&["unused"]),
+    ))
+  }
+
+  fn run_until(app: &mut App, done: impl Fn(&mut App) -> bool) {
+    for _ in 0..1000 {
+      app.update();
+      if done(app) {
+        return;
+      }
+      std::thread::sleep(Duration::from_millis(1));
+    }
+    panic!("Timed out");
+  }
+
+  fn variants(app: &App, entity: bevy_ecs::entity::Entity) -> Option<Vec<String>> {
+    let generator = app.world().get::<TestGenerator>(entity)?;
+    generator.state().map(|state| state.data())
+  }
+
+  #[test]
+  fn generators_rerun_when_rules_change() {
+    let mut app = app("assets".into());
+    let handle = app
+      .world_mut()
+      .resource_mut::<Assets<TestRules>>()
+      .add(rules(&["a"]));
+    let entity = app
+      .world_mut()
+      .spawn((generator(), GeneratorRules(handle.clone())))
+      .id();
+
+    run_until(&mut app, |app| variants(app, entity).is_some());
+    assert!(variants(&app, entity).unwrap().iter().all(|v| v == "a"));
+
+    *app
+      .world_mut()
+      .resource_mut::<Assets<TestRules>>()
+      .get_mut(&handle)
+      .unwrap() = rules(&["b"]);
+
+    run_until(&mut app, |app| {
+      variants(app, entity).is_some_and(|variants| variants.iter().all(|v| v == "b"))
+    });
+  }
+
+  #[cfg(feature = "ron")]
+  #[test]
+  fn tile_sets_are_loaded() {
+    let dir = std::env::temp_dir().join(format!("wfc-tileset-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+      dir.join("grass.tileset.ron"),
+      r#"(tiles: [(variant: "grass", socket: "green", weight: 2.0)])"#,
+    )
+    .unwrap();
+
+    let mut app = app(dir.to_string_lossy().into_owned());
+    let handle: Handle<TestRules> = app
+      .world()
+      .resource::<AssetServer>()
+      .load("grass.tileset.ron");
+    let entity = app
+      .world_mut()
+      .spawn((generator(), GeneratorRules(handle)))
+      .id();
+
+    run_until(&mut app, |app| variants(app, entity).is_some());
+    assert!(variants(&app, entity).unwrap().iter().all(|v| v == "grass"));
+
+    std::fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[cfg(feature = "ron")]
+  #[test]
+  fn symmetric_tile_sets_are_expanded() {
+    let dir = std::env::temp_dir().join(format!("wfc-symmetric-tileset-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+      dir.join("road.tileset.ron"),
+      r#"(tiles: [(variant: "road", socket: "x", sockets: {XNeg: "road", XPos: "road"}, symmetry: I)])"#,
+    )
+    .unwrap();
+
+    let mut app = app_with(
+      dir.to_string_lossy().into_owned(),
+      RulesPlugin::expanded(|variant, orientation| format!("{variant}@{orientation}")),
+    );
+    let handle: Handle<TestRules> = app
+      .world()
+      .resource::<AssetServer>()
+      .load("road.tileset.ron");
+
+    run_until(&mut app, |app| {
+      app
+        .world()
+        .resource::<Assets<TestRules>>()
+        .contains(&handle)
+    });
+    let rules = app.world().resource::<Assets<TestRules>>();
+    let mut variants = rules
+      .get(&handle)
+      .unwrap()
+      .variants()
+      .cloned()
+      .collect::<Vec<_>>();
+    variants.sort();
+    assert_eq!(variants, ["road", "road@1"]);
+
+    std::fs::remove_dir_all(dir).unwrap();
+  }
+}
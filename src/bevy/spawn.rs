@@ -0,0 +1,278 @@
+//! Spawning entities for the cells of a collapsed state.
+//!
+//! Grid axes map to world X, Y and Z in order, matching the Y-up [`Dim2d`](crate::prebuilt::dims::bevy::Dim2d)
+//! and [`Dim3d`](crate::prebuilt::dims::bevy::Dim3d), so their positive directions point along the positive world
+//! axes. Cells are spawned as children of a grid entity with a [`GridCell`], and are placed relative to it
+
+use crate::{
+  Dimension, DirectionalConstraint, Observer, Region, Size, Socket, State, UPos, Variant,
+  prebuilt::regions::Bounds,
+};
+use bevy_ecs::prelude::*;
+use bevy_math::Vec3;
+use bevy_transform::components::Transform;
+
+/// Marks an entity spawned for a cell of a grid
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GridCell<const DIM: usize> {
+  pub grid: Entity,
+  pub position: [usize; DIM],
+}
+
+/// Spawns a bundle for every collapsed cell, using a function from variants to bundles.
+/// Variants the function returns `None` for are skipped
+pub struct CellSpawner<F> {
+  bundle: F,
+  cell_size: Vec3,
+  origin: Vec3,
+}
+
+impl<F> CellSpawner<F> {
+  pub fn new(bundle: F) -> Self {
+    Self {
+      bundle,
+      cell_size: Vec3::ONE,
+      origin: Vec3::ZERO,
+    }
+  }
+
+  /// The distance between cells along each axis
+  pub fn with_cell_size(mut self, cell_size: impl Into<Vec3>) -> Self {
+    self.cell_size = cell_size.into();
+    self
+  }
+
+  /// The translation of the first cell, relative to the grid entity
+  pub fn with_origin(mut self, origin: impl Into<Vec3>) -> Self {
+    self.origin = origin.into();
+    self
+  }
+
+  /// The translation of the cell at the position, relative to the grid entity
+  pub fn translation<const DIM: usize>(&self, position: [usize; DIM]) -> Vec3 {
+    let mut offset = Vec3::ZERO;
+    for (axis, coord) in position.into_iter().take(3).enumerate() {
+      offset[axis] = coord as f32 * self.cell_size[axis];
+    }
+    self.origin + offset
+  }
+
+  /// Spawns every collapsed cell, cells are in the same order as [`State::data_raw`]
+  pub fn spawn_cells<V, B, const DIM: usize>(
+    &self,
+    commands: &mut Commands,
+    grid: Entity,
+    cells: &[Option<V>],
+    size: Size<DIM>,
+  ) -> Vec<Entity>
+  where
+    F: Fn(&V) -> Option<B>,
+    B: Bundle,
+  {
+    let everything = Bounds::new([0; DIM], std::array::from_fn(|axis| size[axis]));
+    self.spawn_region(commands, grid, cells, size, everything)
+  }
+
+  pub fn spawn_state<O, C, V, D, S, B, const DIM: usize>(
+    &self,
+    commands: &mut Commands,
+    grid: Entity,
+    state: &State<O, C, V, D, S, DIM>,
+  ) -> Vec<Entity>
+  where
+    O: Observer<V>,
+    C: DirectionalConstraint<V, D, S>,
+    V: Variant,
+    D: Dimension,
+    S: Socket,
+    F: Fn(&V) -> Option<B>,
+    B: Bundle,
+  {
+    self.spawn_cells(commands, grid, &state.data_raw(), *state.size())
+  }
+
+  /// Replaces the entities of the cells within the region, for after a region has been regenerated
+  pub fn respawn_state<O, C, V, D, S, B, const DIM: usize>(
+    &self,
+    commands: &mut Commands,
+    spawned: &Query<(Entity, &GridCell<DIM>)>,
+    grid: Entity,
+    state: &State<O, C, V, D, S, DIM>,
+    region: impl Region<DIM>,
+  ) -> Vec<Entity>
+  where
+    O: Observer<V>,
+    C: DirectionalConstraint<V, D, S>,
+    V: Variant,
+    D: Dimension,
+    S: Socket,
+    F: Fn(&V) -> Option<B>,
+    B: Bundle,
+  {
+    despawn_region(commands, spawned, grid, &region);
+    self.spawn_region(commands, grid, &state.data_raw(), *state.size(), region)
+  }
+
+  fn spawn_region<V, B, const DIM: usize>(
+    &self,
+    commands: &mut Commands,
+    grid: Entity,
+    cells: &[Option<V>],
+    size: Size<DIM>,
+    region: impl Region<DIM>,
+  ) -> Vec<Entity>
+  where
+    F: Fn(&V) -> Option<B>,
+    B: Bundle,
+  {
+    let mut entities = Vec::new();
+
+    for (i, cell) in cells.iter().enumerate() {
+      let pos = UPos::from_index(i, size);
+      if !region.contains(&pos) {
+        continue;
+      }
+
+      let Some(bundle) = cell.as_ref().and_then(&self.bundle) else {
+        continue;
+      };
+
+      let position: [usize; DIM] = std::array::from_fn(|axis| pos[axis]);
+      let entity = commands
+        .spawn((
+          bundle,
+          Transform::from_translation(self.translation(position)),
+          GridCell { grid, position },
+          ChildOf(grid),
+        ))
+        .id();
+      entities.push(entity);
+    }
+
+    entities
+  }
+}
+
+/// Despawns the entities of a grid that are within the region
+pub fn despawn_region<const DIM: usize>(
+  commands: &mut Commands,
+  spawned: &Query<(Entity, &GridCell<DIM>)>,
+  grid: Entity,
+  region: &impl Region<DIM>,
+) {
+  for (entity, cell) in spawned {
+    if cell.grid == grid && region.contains(&UPos::new(cell.position)) {
+      commands.entity(entity).despawn();
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{CellSpawner, GridCell};
+  use crate::{
+    RuleBuilder, Rules, StateBuilder,
+    prebuilt::{
+      constraints::UnaryConstraint, dims::bevy::Dim2d, processing::RandomObserver, regions::Bounds,
+    },
+  };
+  use bevy_ecs::{prelude::*, system::RunSystemOnce};
+  use bevy_math::Vec3;
+  use bevy_transform::components::Transform;
+
+  #[derive(Component, Debug, PartialEq)]
+  struct Tile(u8);
+
+  #[test]
+  fn cells_are_placed_along_world_axes() {
+    let spawner = CellSpawner::new(|variant: &u8| Some(Tile(*variant)))
+      .with_cell_size([2.0, 3.0, 1.0])
+      .with_origin([1.0, 0.0, 0.0]);
+
+    let mut world = World::new();
+    let grid = world.spawn_empty().id();
+    world
+      .run_system_once(move |mut commands: Commands| {
+        spawner.spawn_cells(
+          &mut commands,
+          grid,
+          &[Some(0), None, Some(1), Some(2)],
+          [2, 2].into(),
+        );
+      })
+      .unwrap();
+
+    let mut cells = world.query::<(&Tile, &Transform, &GridCell<2>, &ChildOf)>();
+    let mut cells = cells
+      .iter(&world)
+      .map(|(tile, transform, cell, parent)| {
+        assert_eq!(parent.parent(), grid);
+        (tile.0, transform.translation, cell.position)
+      })
+      .collect::<Vec<_>>();
+    cells.sort_by_key(|(tile, ..)| *tile);
+
+    assert_eq!(
+      cells,
+      vec![
+        (0, Vec3::new(1.0, 0.0, 0.0), [0, 0]),
+        (1, Vec3::new(1.0, 3.0, 0.0), [0, 1]),
+        (2, Vec3::new(3.0, 3.0, 0.0), [1, 1]),
+      ]
+    );
+  }
+
+  #[test]
+  fn regions_are_respawned() {
+    let rules: Rules<u8, Dim2d, u8> = RuleBuilder::default().with_rule(0, |_| 0).into();
+    let mut state = StateBuilder::new([4, 4], RandomObserver::new(Some(1)), UnaryConstraint, rules)
+      .build()
+      .unwrap();
+    crate::collapse(&mut state).unwrap();
+
+    let mut world = World::new();
+    let grid = world.spawn_empty().id();
+    let other = world.spawn_empty().id();
+
+    let spawner = CellSpawner::new(|variant: &u8| Some(Tile(*variant)));
+    world
+      .run_system_once(move |mut commands: Commands| {
+        spawner.spawn_state(&mut commands, grid, &state);
+        spawner.spawn_state(&mut commands, other, &state);
+      })
+      .unwrap();
+
+    let rules: Rules<u8, Dim2d, u8> = RuleBuilder::default().with_rule(1, |_| 0).into();
+    let mut state = StateBuilder::new([4, 4], RandomObserver::new(Some(1)), UnaryConstraint, rules)
+      .build()
+      .unwrap();
+    crate::collapse(&mut state).unwrap();
+
+    let spawner = CellSpawner::new(|variant: &u8| Some(Tile(*variant)));
+    world
+      .run_system_once(
+        move |mut commands: Commands, spawned: Query<(Entity, &GridCell<2>)>| {
+          let entities = spawner.respawn_state(
+            &mut commands,
+            &spawned,
+            grid,
+            &state,
+            Bounds::new([0, 0], [2, 2]),
+          );
+          assert_eq!(entities.len(), 4);
+        },
+      )
+      .unwrap();
+
+    let mut cells = world.query::<(&Tile, &GridCell<2>)>();
+    let mut count = |grid: Entity, tile: u8| {
+      cells
+        .iter(&world)
+        .filter(|(t, cell)| cell.grid == grid && t.0 == tile)
+        .count()
+    };
+    assert_eq!(count(grid, 0), 12);
+    assert_eq!(count(grid, 1), 4);
+    assert_eq!(count(other, 0), 16);
+  }
+}
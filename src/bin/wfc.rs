@@ -0,0 +1,403 @@
+//! Runs generations described by a config file, so tile sets can be iterated on without writing any code.
+//!
+//! The config may be TOML, RON or JSON, chosen by its extension, and looks like
+//!
+//! ```toml
+//! tileset = "roads.ron"
+//! size = [32, 16]
+//! seed = 42
+//! retries = 10
+//! fixed = [{ position = [0, 0], variant = "road" }]
+//!
+//! [output]
+//! format = "text"
+//! tokens = { grass = ".", road = "=" }
+//! colors = { grass = [40, 160, 40, 255], road = [90, 90, 90, 255] }
+//! ```
+//!
+//! Tiles with a symmetry are expanded into one variant per orientation, named `variant@orientation`
+//! after the first, which need their own tokens and colors.
+
+use clap::{Parser, ValueEnum};
+use serde::{Deserialize, de::DeserializeOwned};
+use std::{
+  collections::{BTreeMap, HashMap},
+  error::Error,
+  path::{Path, PathBuf},
+  time::{Duration, Instant},
+};
+use wfc::{
+  Modifier,
+  io::{image::render_pixels, text::TextGrid, tileset::TileSet},
+  prebuilt::{Dim2d, processing::WeightedObserver},
+  prelude::*,
+};
+
+type Tiles = TileSet<String, Dim2d, String>;
+
+#[derive(Parser)]
+#[command(
+  version,
+  about = "Generates grids from a tile set described by a config file"
+)]
+struct Args {
+  /// Path to the config file
+  config: PathBuf,
+  /// Overrides the seed of the config
+  #[arg(long)]
+  seed: Option<u64>,
+  /// Generates this many outputs into the output directory, each starting from a seed `retries + 1`
+  /// past the previous one so retries never reuse the seed of another output
+  #[arg(long)]
+  count: Option<u64>,
+  /// Directory for batch outputs, named after their seed
+  #[arg(long, default_value = ".")]
+  out_dir: PathBuf,
+  /// Overrides the output format of the config
+  #[arg(long)]
+  format: Option<Format>,
+  /// Prints statistics about every generation
+  #[arg(long)]
+  stats: bool,
+  /// Writes collapse, contradiction and retry events as JSON lines
+  #[cfg(feature = "profiling")]
+  #[arg(long)]
+  metrics: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+struct Config {
+  /// Path to the tile set, relative to the config
+  tileset: PathBuf,
+  size: [usize; 2],
+  #[serde(default)]
+  seed: Option<u64>,
+  #[serde(default = "default_retries")]
+  retries: u64,
+  #[serde(default)]
+  fixed: Vec<Fixed>,
+  #[serde(default)]
+  output: Output,
+}
+
+fn default_retries() -> u64 {
+  10
+}
+
+#[derive(Deserialize)]
+struct Fixed {
+  position: [usize; 2],
+  variant: String,
+}
+
+#[derive(Deserialize, Default)]
+struct Output {
+  #[serde(default)]
+  format: Format,
+  /// Written to stdout when absent, images always require a path
+  #[serde(default)]
+  path: Option<PathBuf>,
+  /// Text for each variant, defaults to the first character of the variant.
+  /// Every variant must end up with a distinct token
+  #[serde(default)]
+  tokens: HashMap<String, String>,
+  /// Color for each variant when writing images
+  #[serde(default)]
+  colors: HashMap<String, [u8; 4]>,
+}
+
+#[derive(Deserialize, ValueEnum, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Format {
+  #[default]
+  Text,
+  Json,
+  Image,
+}
+
+impl Format {
+  fn extension(&self) -> &'static str {
+    match self {
+      Self::Text => "txt",
+      Self::Json => "json",
+      Self::Image => "png",
+    }
+  }
+}
+
+struct Generated {
+  seed: u64,
+  elapsed: Duration,
+  stats: Stats,
+  cells: Vec<String>,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+  let args = Args::parse();
+
+  #[cfg(feature = "profiling")]
+  let _guards = match &args.metrics {
+    Some(path) => wfc::perf::Exporter::default()
+      .with_metrics_path(path)
+      .install()?,
+    None => Vec::new(),
+  };
+
+  let config: Config = load(&args.config)?;
+  let base = args.config.parent().unwrap_or(Path::new("."));
+  let tiles = load::<Tiles>(&base.join(&config.tileset))?
+    .expanded(|variant, orientation| format!("{variant}@{orientation}"));
+
+  let format = args.format.unwrap_or(config.output.format);
+  validate(&config, &tiles, format)?;
+  let seed = args
+    .seed
+    .or(config.seed)
+    .unwrap_or_else(rand::random::<u64>);
+
+  if format == Format::Image
+    && let Some(tile) = tiles
+      .tiles
+      .iter()
+      .find(|tile| !config.output.colors.contains_key(&tile.variant))
+  {
+    return Err(format!("No color for variant {:?}", tile.variant).into());
+  }
+
+  match args.count {
+    Some(count) => {
+      std::fs::create_dir_all(&args.out_dir)?;
+
+      let stride = config.retries.wrapping_add(1);
+      for seed in (0..count).map(|i| seed.wrapping_add(i.wrapping_mul(stride))) {
+        let generated = generate(&tiles, &config, seed)?;
+        let path = args
+          .out_dir
+          .join(format!("{}.{}", generated.seed, format.extension()));
+        write(&generated, &tiles, &config, format, Some(&path))?;
+
+        if args.stats {
+          print_stats(&generated);
+        }
+      }
+    }
+    None => {
+      let generated = generate(&tiles, &config, seed)?;
+      write(
+        &generated,
+        &tiles,
+        &config,
+        format,
+        config.output.path.as_deref(),
+      )?;
+
+      if args.stats {
+        print_stats(&generated);
+      }
+    }
+  }
+
+  Ok(())
+}
+
+/// Loads any deserializable file, choosing the format by its extension
+fn load<T: DeserializeOwned>(path: &Path) -> Result<T, Box<dyn Error>> {
+  let text =
+    std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+
+  Ok(match path.extension().and_then(|ext| ext.to_str()) {
+    Some("ron") => ron::Options::default()
+      .with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME)
+      .from_str(&text)?,
+    Some("json") => serde_json::from_str(&text)?,
+    Some("toml") => toml::from_str(&text)?,
+    _ => return Err(format!("Unknown format for {}", path.display()).into()),
+  })
+}
+
+/// Checks the config against the tile set so mistakes are reported instead of panicking or
+/// producing ambiguous output
+fn validate(config: &Config, tiles: &Tiles, format: Format) -> Result<(), Box<dyn Error>> {
+  for fixed in &config.fixed {
+    if fixed.position[0] >= config.size[0] || fixed.position[1] >= config.size[1] {
+      return Err(
+        format!(
+          "Fixed {:?} at {:?} is outside of the size {:?}",
+          fixed.variant, fixed.position, config.size
+        )
+        .into(),
+      );
+    }
+  }
+
+  if format != Format::Text {
+    return Ok(());
+  }
+
+  let mut variants = HashMap::<String, &String>::new();
+  for tile in &tiles.tiles {
+    let token = token(config, &tile.variant);
+    if let Some(other) = variants.insert(token.clone(), &tile.variant)
+      && other != &tile.variant
+    {
+      return Err(
+        format!(
+          "Variants {other:?} and {:?} share the token {token:?}, set distinct output tokens",
+          tile.variant
+        )
+        .into(),
+      );
+    }
+  }
+
+  Ok(())
+}
+
+/// The text token of a variant
+fn token(config: &Config, variant: &str) -> String {
+  config
+    .output
+    .tokens
+    .get(variant)
+    .cloned()
+    .unwrap_or_else(|| variant.chars().take(1).collect())
+}
+
+/// Generates with the seed, moving on to the following seeds when the collapse fails.
+/// Failing to build the state is returned immediately as no seed can fix it
+fn generate(tiles: &Tiles, config: &Config, seed: u64) -> Result<Generated, Box<dyn Error>> {
+  let rules = tiles.rules()?;
+  let constraint = tiles.constraint();
+  let start = Instant::now();
+  let mut stats = Stats::default();
+  let mut last_error = None;
+
+  for attempt in 0..=config.retries {
+    let seed = seed.wrapping_add(attempt);
+    #[cfg(feature = "profiling")]
+    if attempt > 0 {
+      wfc::perf::retry(seed, attempt);
+    }
+    let observer = WeightedObserver::new(Some(seed), tiles.weights()).chain(tiles.limits());
+
+    let mut builder = StateBuilder::new(config.size, observer, constraint.clone(), rules.clone());
+    for fixed in &config.fixed {
+      builder.insert(fixed.position, fixed.variant.clone());
+    }
+
+    let mut state = builder
+      .build()
+      .map_err(|e| format!("Failed to set up the fixed cells: {e}"))?;
+
+    match wfc::collapse(&mut state) {
+      Ok(attempt_stats) => {
+        stats += attempt_stats;
+        return Ok(Generated {
+          seed,
+          elapsed: start.elapsed(),
+          stats,
+          cells: state.data(),
+        });
+      }
+      Err(e) => {
+        // the last attempt is not followed by a retry
+        if attempt < config.retries {
+          stats.add_retry(*state.stats());
+        } else {
+          stats += *state.stats();
+        }
+        last_error = Some(e);
+      }
+    }
+  }
+
+  Err(
+    format!(
+      "Failed after {} attempts: {}",
+      config.retries + 1,
+      last_error.map(|e| e.to_string()).unwrap_or_default()
+    )
+    .into(),
+  )
+}
+
+fn write(
+  generated: &Generated,
+  tiles: &Tiles,
+  config: &Config,
+  format: Format,
+  path: Option<&Path>,
+) -> Result<(), Box<dyn Error>> {
+  let size = Size::new(config.size);
+
+  let text = match format {
+    Format::Text => {
+      let grid = tiles.tiles.iter().fold(TextGrid::default(), |grid, tile| {
+        grid.with_token(token(config, &tile.variant), tile.variant.clone())
+      });
+
+      grid.render(&generated.cells, size)? + "\n"
+    }
+    Format::Json => {
+      let rows = generated
+        .cells
+        .chunks(config.size[0])
+        .map(|row| row.to_vec())
+        .collect::<Vec<_>>();
+
+      serde_json::to_string_pretty(&serde_json::json!({
+        "seed": generated.seed,
+        "size": config.size,
+        "rows": rows,
+      }))?
+    }
+    Format::Image => {
+      let path = path.ok_or("Image output requires a path")?;
+      let cells = generated
+        .cells
+        .iter()
+        .cloned()
+        .map(Some)
+        .collect::<Vec<_>>();
+      render_pixels(&cells, size, |variant| config.output.colors[variant]).save(path)?;
+      return Ok(());
+    }
+  };
+
+  match path {
+    Some(path) => std::fs::write(path, text)?,
+    None => print!("{text}"),
+  }
+
+  Ok(())
+}
+
+fn print_stats(generated: &Generated) {
+  let mut counts = BTreeMap::<&str, usize>::new();
+  for cell in &generated.cells {
+    *counts.entry(cell).or_default() += 1;
+  }
+
+  let stats = &generated.stats;
+  eprintln!(
+    "seed {} succeeded after {} attempt(s) in {:?}",
+    generated.seed,
+    stats.retries + 1,
+    generated.elapsed
+  );
+  eprintln!(
+    "  {} observations in {:?}, {} propagation steps removing {} possibilities in {:?}",
+    stats.observations,
+    stats.observe_time,
+    stats.propagation_steps,
+    stats.removed_possibilities,
+    stats.propagate_time
+  );
+  eprintln!(
+    "  socket cache {} hits, {} misses, max stack depth {}, {} contradiction(s)",
+    stats.cache_hits, stats.cache_misses, stats.max_stack_depth, stats.contradictions
+  );
+  for (variant, count) in counts {
+    eprintln!("  {variant}: {count}");
+  }
+}
@@ -1,6 +1,6 @@
 use crate::{
   CellIndex, Dimension, Rules, Socket, UPos, Variant, err,
-  util::{self, IPos, Size},
+  util::{self, IPos, Metric, Size},
 };
 use derive_more::derive::Deref;
 use ordermap::OrderSet;
@@ -21,6 +21,13 @@ pub struct Cells<V: Variant, D: Dimension, const DIM: usize> {
 
   #[cfg_attr(feature = "bevy", reflect(ignore))]
   pub entropy_cache: EntropyCache,
+
+  /// Cells whose possibilities were reduced outside of propagation and still need to be propagated
+  pub(crate) modified: Vec<CellIndex>,
+
+  /// Cells that changed or collapsed but were not yet taken by each watcher, see [`Cells::watch`]
+  #[cfg_attr(feature = "serde", serde(skip))]
+  watchers: Vec<Vec<CellIndex>>,
 }
 
 impl<V: Variant, D: Dimension, const DIM: usize> Cells<V, D, DIM> {
@@ -48,6 +55,8 @@ impl<V: Variant, D: Dimension, const DIM: usize> Cells<V, D, DIM> {
       size,
       list,
       entropy_cache,
+      modified: Vec::new(),
+      watchers: Vec::new(),
     }
   }
 
@@ -65,6 +74,91 @@ impl<V: Variant, D: Dimension, const DIM: usize> Cells<V, D, DIM> {
 
   pub fn set_entropy(&mut self, starting_entropy: usize, index: usize, new_entropy: usize) {
     self.entropy_cache.set(starting_entropy, index, new_entropy);
+    self.record_change(index);
+  }
+
+  /// Starts recording the cells that change or collapse, returning the watcher to pass to [`Cells::take_changes`].
+  /// Lets modifiers follow changes made by propagation without rescanning every cell
+  pub fn watch(&mut self) -> usize {
+    self.watchers.push(Vec::new());
+    self.watchers.len() - 1
+  }
+
+  /// The cells that changed since the watcher last took them, a cell may appear more than once.
+  /// `None` if the watcher was not created by these cells
+  pub fn take_changes(&mut self, watcher: usize) -> Option<Vec<CellIndex>> {
+    self.watchers.get_mut(watcher).map(std::mem::take)
+  }
+
+  fn record_change(&mut self, index: CellIndex) {
+    for changes in &mut self.watchers {
+      changes.push(index);
+    }
+  }
+
+  /// The number of cells that have not been collapsed yet
+  pub fn uncollapsed_count(&self) -> usize {
+    self.entropy_cache.iter().map(|level| level.len()).sum()
+  }
+
+  /// Removes a variant from an uncollapsed cell, see [`Cells::retain`]
+  pub fn remove_variant(&mut self, index: usize, variant: &V) -> Result<bool, err::Error<DIM>> {
+    self.retain(index, |v| v != variant)
+  }
+
+  /// Retains only the possibilities of an uncollapsed cell that pass the predicate
+  ///
+  /// Keeps the entropy cache in sync and queues the cell for propagation if anything was removed.
+  /// Returns true if at least one possibility was removed
+  pub fn retain(
+    &mut self,
+    index: usize,
+    f: impl FnMut(&V) -> bool,
+  ) -> Result<bool, err::Error<DIM>> {
+    let cell = &mut self.list[index];
+    if cell.collapsed() {
+      return Ok(false);
+    }
+
+    let starting_entropy = cell.entropy;
+    cell.possibilities.retain(f);
+
+    if cell.possibilities.is_empty() {
+      return Err(err::Error::Exhausted {
+        position: cell.position,
+      });
+    }
+
+    let entropy = cell.possibilities.len();
+    cell.entropy = entropy;
+
+    if starting_entropy == entropy {
+      return Ok(false);
+    }
+
+    self.set_entropy(starting_entropy, index, entropy);
+    self.modified.push(index);
+
+    Ok(true)
+  }
+
+  /// Acquires the indexes of all cells within the radius of the position, excluding the position itself
+  pub fn indexes_within(&self, pos: &IPos<DIM>, radius: f64, metric: Metric) -> Vec<CellIndex> {
+    pos
+      .within(radius, metric)
+      .filter(|npos| npos != pos && self.size.contains(npos))
+      .map(|npos| npos.index(self.size))
+      .collect()
+  }
+
+  /// Iterates over the indexes of all cells that have not been collapsed yet
+  pub fn uncollapsed_indexes(&self) -> impl Iterator<Item = CellIndex> + '_ {
+    self
+      .list
+      .iter()
+      .enumerate()
+      .filter(|(_, cell)| !cell.collapsed())
+      .map(|(i, _)| i)
   }
 
   /// Acquires a list of uncollapsed cell indexes along a side of this group of cells
@@ -113,6 +207,7 @@ impl<V: Variant, D: Dimension, const DIM: usize> Cells<V, D, DIM> {
     self.entropy_cache.clear_entry(cell.entropy, index);
     // and collapse it to the selected variant
     cell.collapse(variant);
+    self.record_change(index);
 
     Ok(())
   }
@@ -12,6 +12,33 @@ pub enum Error<const DIM: usize> {
   NoRule { variant: usize },
   #[error("No possibilities available due to setup misconfiguration")]
   NoPossibilities,
+  #[error("All possibilities were removed from {position:?}")]
+  Exhausted { position: IPos<DIM> },
+  #[error(
+    "Count of {variant} is unreachable, {required} more required but only {available} cells can hold it"
+  )]
+  CountUnreachable {
+    variant: String,
+    required: usize,
+    available: usize,
+  },
+  #[error("Count of {variant} exceeded, selected {selected} times with a maximum of {max}")]
+  CountExceeded {
+    variant: String,
+    selected: usize,
+    max: usize,
+  },
+  #[error("Proximity rule violated between {position:?} and {neighbor:?}")]
+  ProximityViolated {
+    position: IPos<DIM>,
+    neighbor: IPos<DIM>,
+  },
+  #[error("Proximity requirement of {position:?} can no longer be satisfied")]
+  ProximityUnreachable { position: IPos<DIM> },
+  #[error("Counts are unreachable, {required} more cells required but only {available} remain")]
+  CountsUnreachable { required: usize, available: usize },
+  #[error("Layer has {found} cells but the grid is {expected} cells large")]
+  LayerSizeMismatch { expected: usize, found: usize },
   #[error(
     "Mismatch in dimensions, DIM set to {const_value} and Dimension evaluated to {dimension_count}"
   )]
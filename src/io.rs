@@ -0,0 +1,10 @@
+//! Reading samples from, and writing generated grids to, other formats
+
+#[cfg(feature = "image")]
+pub mod image;
+pub mod text;
+#[cfg(feature = "tiled")]
+pub mod tiled;
+#[cfg(feature = "serde")]
+pub mod tileset;
+pub mod vox;
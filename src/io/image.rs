@@ -0,0 +1,192 @@
+//! PNG samples and rendered output, with the y axis pointing down the image so `Dim2d::Up` is towards the top row
+
+use crate::{
+  Dimension, DirectionalConstraint, Observer, Size, Socket, State, Variant, prebuilt::auto::Sample,
+};
+use image::{GenericImage, GenericImageView, ImageError, ImageReader, RgbaImage};
+use std::{collections::HashMap, fmt::Debug, path::Path};
+
+/// An RGBA color, one byte per channel
+pub type Color = [u8; 4];
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+  #[error(transparent)]
+  Image(#[from] ImageError),
+  #[error(transparent)]
+  Io(#[from] std::io::Error),
+  #[error("No tile for variant {0}")]
+  MissingTile(String),
+  #[error("Tile {0:?} is outside of the atlas")]
+  TileOutOfBounds([u32; 2]),
+}
+
+/// Converts every pixel of the image into a cell of a sample
+pub fn sample_from_image(image: &RgbaImage) -> Sample<Color, 2> {
+  Sample::new(
+    image.pixels().map(|pixel| pixel.0).collect::<Vec<_>>(),
+    [image.width(), image.height()],
+  )
+}
+
+/// Loads an image as a sample, with each pixel becoming a cell
+pub fn load_sample(path: impl AsRef<Path>) -> Result<Sample<Color, 2>, Error> {
+  let image = ImageReader::open(path)?.with_guessed_format()?.decode()?;
+  Ok(sample_from_image(&image.to_rgba8()))
+}
+
+/// Renders one pixel per cell, leaving uncollapsed cells transparent
+pub fn render_pixels<V>(
+  cells: &[Option<V>],
+  size: Size<2>,
+  color: impl Fn(&V) -> Color,
+) -> RgbaImage {
+  let mut image = RgbaImage::new(size[0] as u32, size[1] as u32);
+
+  for (pixel, cell) in image.pixels_mut().zip(cells) {
+    if let Some(variant) = cell {
+      pixel.0 = color(variant);
+    }
+  }
+
+  image
+}
+
+/// Renders one pixel per cell of the state
+pub fn render_state_pixels<O, C, V, D, S>(
+  state: &State<O, C, V, D, S, 2>,
+  color: impl Fn(&V) -> Color,
+) -> RgbaImage
+where
+  O: Observer<V>,
+  C: DirectionalConstraint<V, D, S>,
+  V: Variant,
+  D: Dimension,
+  S: Socket,
+{
+  render_pixels(&state.data_raw(), *state.size(), color)
+}
+
+/// An image containing a grid of equally sized tiles, along with the tile used for each variant
+#[derive(Debug, Clone)]
+pub struct TileAtlas<V: Variant> {
+  image: RgbaImage,
+  tile_size: [u32; 2],
+  tiles: HashMap<V, [u32; 2]>,
+}
+
+impl<V: Variant> TileAtlas<V> {
+  pub fn new(image: RgbaImage, tile_size: [u32; 2]) -> Self {
+    Self {
+      image,
+      tile_size,
+      tiles: Default::default(),
+    }
+  }
+
+  pub fn load(path: impl AsRef<Path>, tile_size: [u32; 2]) -> Result<Self, Error> {
+    let image = ImageReader::open(path)?.with_guessed_format()?.decode()?;
+    Ok(Self::new(image.to_rgba8(), tile_size))
+  }
+
+  /// Uses the tile at the column and row of the atlas for the variant
+  pub fn add_tile(&mut self, variant: V, tile: [u32; 2]) -> &mut Self {
+    self.tiles.insert(variant, tile);
+    self
+  }
+
+  pub fn with_tile(mut self, variant: V, tile: [u32; 2]) -> Self {
+    self.add_tile(variant, tile);
+    self
+  }
+
+  pub fn tile_size(&self) -> [u32; 2] {
+    self.tile_size
+  }
+
+  /// Blits the tile of every cell, leaving uncollapsed cells transparent
+  pub fn render(&self, cells: &[Option<V>], size: Size<2>) -> Result<RgbaImage, Error> {
+    let [width, height] = self.tile_size;
+    let mut output = RgbaImage::new(size[0] as u32 * width, size[1] as u32 * height);
+
+    for (i, cell) in cells.iter().enumerate() {
+      let Some(variant) = cell else {
+        continue;
+      };
+
+      let [column, row] = *self
+        .tiles
+        .get(variant)
+        .ok_or_else(|| Error::MissingTile(format!("{variant:?}")))?;
+
+      if (column + 1) * width > self.image.width() || (row + 1) * height > self.image.height() {
+        return Err(Error::TileOutOfBounds([column, row]));
+      }
+
+      let tile = self.image.view(column * width, row * height, width, height);
+      let x = (i % size[0]) as u32 * width;
+      let y = (i / size[0]) as u32 * height;
+      output.copy_from(&*tile, x, y)?;
+    }
+
+    Ok(output)
+  }
+
+  /// Blits the tile of every cell of the state
+  pub fn render_state<O, C, D, S>(
+    &self,
+    state: &State<O, C, V, D, S, 2>,
+  ) -> Result<RgbaImage, Error>
+  where
+    O: Observer<V>,
+    C: DirectionalConstraint<V, D, S>,
+    D: Dimension,
+    S: Socket,
+  {
+    self.render(&state.data_raw(), *state.size())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{Color, TileAtlas, load_sample, render_pixels, sample_from_image};
+  use image::{Rgba, RgbaImage};
+
+  const RED: Color = [255, 0, 0, 255];
+  const BLUE: Color = [0, 0, 255, 255];
+
+  #[test]
+  fn images_round_trip() {
+    let image = RgbaImage::from_fn(3, 2, |x, y| Rgba(if x == y { RED } else { BLUE }));
+
+    let path = std::env::temp_dir().join("wfc_images_round_trip.png");
+    image.save(&path).unwrap();
+    let sample = load_sample(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(sample.source, sample_from_image(&image).source);
+    assert_eq!(sample.source, vec![RED, BLUE, BLUE, BLUE, RED, BLUE]);
+
+    let cells = sample.source.iter().cloned().map(Some).collect::<Vec<_>>();
+    assert_eq!(render_pixels(&cells, sample.size, |color| *color), image);
+  }
+
+  #[test]
+  fn tiles_are_blitted() {
+    let atlas_image = RgbaImage::from_fn(4, 2, |x, _| Rgba(if x < 2 { RED } else { BLUE }));
+    let atlas = TileAtlas::new(atlas_image, [2, 2])
+      .with_tile('r', [0, 0])
+      .with_tile('b', [1, 0]);
+
+    let output = atlas
+      .render(&[Some('b'), None, Some('r')], [3, 1].into())
+      .unwrap();
+
+    assert_eq!(output.dimensions(), (6, 2));
+    assert_eq!(output.get_pixel(1, 1).0, BLUE);
+    assert_eq!(output.get_pixel(3, 0).0, [0, 0, 0, 0]);
+    assert_eq!(output.get_pixel(4, 1).0, RED);
+
+    assert!(atlas.render(&[Some('x')], [1, 1].into()).is_err());
+  }
+}
@@ -0,0 +1,403 @@
+//! Text grids, where each line is a row along the x axis and 3D slices along the z axis are separated by empty lines.
+//! Lines made of whitespace are rows, so whitespace may be used as a token
+//!
+//! By default the first line is y = 0, matching the layout of the source strings used throughout the examples
+
+use crate::{
+  Dimension, DirectionalConstraint, Observer, Size, Socket, State, Variant, prebuilt::auto::Sample,
+};
+use std::collections::HashMap;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+  #[error("Unrecognized text {text:?} at line {line}, column {column}")]
+  UnknownToken {
+    line: usize,
+    column: usize,
+    text: String,
+  },
+  #[error("Line {line} has {found} cells but {expected} were expected")]
+  RaggedRow {
+    line: usize,
+    expected: usize,
+    found: usize,
+  },
+  #[error("Slice starting at line {line} has {found} rows but {expected} were expected")]
+  RaggedSlice {
+    line: usize,
+    expected: usize,
+    found: usize,
+  },
+  #[error("The text has more dimensions than the {0} requested")]
+  TooManyDimensions(usize),
+  #[error("Text grids only support up to 3 dimensions, {0} were requested")]
+  UnsupportedDimension(usize),
+  #[error("No token for variant {0}")]
+  MissingToken(String),
+  #[error("Expected {expected} cells for the size but {found} were given")]
+  SizeMismatch { expected: usize, found: usize },
+}
+
+/// Converts between text and grids of variants through a legend of tokens.
+/// Tokens may be more than one character long, the longest matching token is always used
+#[derive(Debug, Clone)]
+pub struct TextGrid<V: Variant> {
+  tokens: Vec<(String, V)>,
+  names: HashMap<V, String>,
+  unknown: String,
+  flip_y: bool,
+  flip_z: bool,
+}
+
+impl<V: Variant> Default for TextGrid<V> {
+  fn default() -> Self {
+    Self {
+      tokens: Default::default(),
+      names: Default::default(),
+      unknown: String::from("?"),
+      flip_y: false,
+      flip_z: false,
+    }
+  }
+}
+
+impl TextGrid<char> {
+  /// A legend where each character is its own variant
+  pub fn chars(chars: impl IntoIterator<Item = char>) -> Self {
+    chars
+      .into_iter()
+      .fold(Self::default(), |grid, c| grid.with_token(c.to_string(), c))
+  }
+}
+
+impl<V: Variant> TextGrid<V> {
+  /// Maps the token to the variant. When rendering, the first token added for a variant is used.
+  /// Empty tokens are ignored, as they would match anywhere without consuming any text
+  pub fn add_token(&mut self, token: impl Into<String>, variant: V) -> &mut Self {
+    let token = token.into();
+    if token.is_empty() {
+      return self;
+    }
+
+    self.names.entry(variant.clone()).or_insert(token.clone());
+    self.tokens.retain(|(existing, _)| *existing != token);
+    self.tokens.push((token, variant));
+    self
+      .tokens
+      .sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+    self
+  }
+
+  pub fn with_token(mut self, token: impl Into<String>, variant: V) -> Self {
+    self.add_token(token, variant);
+    self
+  }
+
+  /// The text used to render cells that have not collapsed yet
+  pub fn with_unknown(mut self, unknown: impl Into<String>) -> Self {
+    self.unknown = unknown.into();
+    self
+  }
+
+  /// Makes the first line the highest y instead of y = 0
+  pub fn with_flip_y(mut self, flip_y: bool) -> Self {
+    self.flip_y = flip_y;
+    self
+  }
+
+  /// Makes the first slice the highest z instead of z = 0
+  pub fn with_flip_z(mut self, flip_z: bool) -> Self {
+    self.flip_z = flip_z;
+    self
+  }
+
+  /// Parses the text into a sample. Any dimensions beyond those in the text have a size of 1
+  pub fn parse<const DIM: usize>(&self, text: &str) -> Result<Sample<V, DIM>, Error> {
+    if DIM > 3 {
+      return Err(Error::UnsupportedDimension(DIM));
+    }
+
+    let mut slices = Vec::<(usize, Vec<Vec<V>>)>::new();
+    let mut current = None::<(usize, Vec<Vec<V>>)>;
+
+    for (i, line) in text.lines().enumerate() {
+      let line = line.strip_suffix('\r').unwrap_or(line);
+
+      if line.is_empty() {
+        slices.extend(current.take());
+        continue;
+      }
+
+      let row = self.tokenize(line, i + 1)?;
+      current
+        .get_or_insert_with(|| (i + 1, Vec::new()))
+        .1
+        .push(row);
+    }
+
+    slices.extend(current);
+
+    let depth = slices.len();
+    let height = slices
+      .first()
+      .map(|(_, rows)| rows.len())
+      .unwrap_or_default();
+    let width = slices
+      .first()
+      .and_then(|(_, rows)| rows.first())
+      .map(Vec::len)
+      .unwrap_or_default();
+
+    for (start, rows) in &slices {
+      if rows.len() != height {
+        return Err(Error::RaggedSlice {
+          line: *start,
+          expected: height,
+          found: rows.len(),
+        });
+      }
+
+      for (y, row) in rows.iter().enumerate() {
+        if row.len() != width {
+          return Err(Error::RaggedRow {
+            line: start + y,
+            expected: width,
+            found: row.len(),
+          });
+        }
+      }
+    }
+
+    let extents = [width, height, depth];
+    if extents[DIM..].iter().any(|extent| *extent > 1) {
+      return Err(Error::TooManyDimensions(DIM));
+    }
+
+    let mut source = Vec::with_capacity(width * height * depth);
+    for z in 0..depth {
+      let (_, rows) = &slices[Self::flip(z, depth, self.flip_z)];
+      for y in 0..height {
+        source.extend(rows[Self::flip(y, height, self.flip_y)].iter().cloned());
+      }
+    }
+
+    Ok(Sample::new(
+      source,
+      Size::new(std::array::from_fn(|i| extents[i])),
+    ))
+  }
+
+  /// Renders the cells as text, rows separated by new lines and slices by empty lines
+  pub fn render<const DIM: usize>(&self, cells: &[V], size: Size<DIM>) -> Result<String, Error> {
+    self.render_with(cells, size, |variant| {
+      self
+        .names
+        .get(variant)
+        .map(String::as_str)
+        .ok_or_else(|| Error::MissingToken(format!("{variant:?}")))
+    })
+  }
+
+  /// Renders the state, using the unknown token for cells that have not collapsed yet
+  pub fn render_state<O, C, D, S, const DIM: usize>(
+    &self,
+    state: &State<O, C, V, D, S, DIM>,
+  ) -> Result<String, Error>
+  where
+    O: Observer<V>,
+    C: DirectionalConstraint<V, D, S>,
+    D: Dimension,
+    S: Socket,
+  {
+    self.render_with(&state.data_raw(), *state.size(), |cell| match cell {
+      Some(variant) => self
+        .names
+        .get(variant)
+        .map(String::as_str)
+        .ok_or_else(|| Error::MissingToken(format!("{variant:?}"))),
+      None => Ok(self.unknown.as_str()),
+    })
+  }
+
+  fn render_with<'s, T, const DIM: usize>(
+    &self,
+    cells: &[T],
+    size: Size<DIM>,
+    token: impl Fn(&T) -> Result<&'s str, Error>,
+  ) -> Result<String, Error> {
+    if DIM > 3 {
+      return Err(Error::UnsupportedDimension(DIM));
+    }
+
+    if cells.len() != size.len() {
+      return Err(Error::SizeMismatch {
+        expected: size.len(),
+        found: cells.len(),
+      });
+    }
+
+    let extent = |i: usize| if i < DIM { size[i] } else { 1 };
+    let (width, height, depth) = (extent(0), extent(1), extent(2));
+
+    let mut slices = Vec::with_capacity(depth);
+    for z in 0..depth {
+      let z = Self::flip(z, depth, self.flip_z);
+      let mut rows = Vec::with_capacity(height);
+      for y in 0..height {
+        let y = Self::flip(y, height, self.flip_y);
+        let start = (z * height + y) * width;
+        rows.push(
+          cells[start..start + width]
+            .iter()
+            .map(&token)
+            .collect::<Result<String, _>>()?,
+        );
+      }
+      slices.push(rows.join("\n"));
+    }
+
+    Ok(slices.join("\n\n"))
+  }
+
+  fn tokenize(&self, line: &str, line_number: usize) -> Result<Vec<V>, Error> {
+    let mut row = Vec::new();
+    let mut rest = line;
+
+    while !rest.is_empty() {
+      let Some((token, variant)) = self
+        .tokens
+        .iter()
+        .find(|(token, _)| rest.starts_with(token.as_str()))
+      else {
+        return Err(Error::UnknownToken {
+          line: line_number,
+          column: line[..line.len() - rest.len()].chars().count() + 1,
+          text: rest.chars().next().map(String::from).unwrap_or_default(),
+        });
+      };
+
+      row.push(variant.clone());
+      rest = &rest[token.len()..];
+    }
+
+    Ok(row)
+  }
+
+  fn flip(i: usize, len: usize, flip: bool) -> usize {
+    if flip { len - 1 - i } else { i }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{Error, TextGrid};
+
+  #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+  enum Terrain {
+    Water,
+    Sand,
+    Mountain,
+  }
+
+  fn terrain() -> TextGrid<Terrain> {
+    TextGrid::default()
+      .with_token("~~", Terrain::Water)
+      .with_token("~", Terrain::Sand)
+      .with_token("/\\", Terrain::Mountain)
+  }
+
+  #[test]
+  fn multi_char_tokens_round_trip() {
+    let text = "~~~/\\\n~~~~~";
+    let sample = terrain().parse::<2>(text).unwrap();
+
+    assert_eq!((sample.size[0], sample.size[1]), (3, 2));
+    assert_eq!(
+      sample.source,
+      vec![
+        Terrain::Water,
+        Terrain::Sand,
+        Terrain::Mountain,
+        Terrain::Water,
+        Terrain::Water,
+        Terrain::Sand
+      ]
+    );
+
+    assert_eq!(terrain().render(&sample.source, sample.size).unwrap(), text);
+  }
+
+  #[test]
+  fn slices_and_orientation() {
+    let grid = TextGrid::chars("abcd".chars()).with_flip_y(true);
+    let text = "ab\ncd\n\nba\ndc\n";
+
+    let sample = grid.parse::<3>(text).unwrap();
+
+    assert_eq!((sample.size[0], sample.size[1], sample.size[2]), (2, 2, 2));
+    assert_eq!(sample.source, vec!['c', 'd', 'a', 'b', 'd', 'c', 'b', 'a']);
+    assert_eq!(
+      grid.render(&sample.source, sample.size).unwrap(),
+      text.trim_end()
+    );
+
+    assert!(matches!(
+      grid.parse::<2>(text),
+      Err(Error::TooManyDimensions(2))
+    ));
+  }
+
+  #[test]
+  fn whitespace_rows_are_not_separators() {
+    let grid = TextGrid::chars(" #".chars());
+    let text = "# \n  \n\n  \n #";
+
+    let sample = grid.parse::<3>(text).unwrap();
+
+    assert_eq!((sample.size[0], sample.size[1], sample.size[2]), (2, 2, 2));
+    assert_eq!(sample.source, vec!['#', ' ', ' ', ' ', ' ', ' ', ' ', '#']);
+    assert_eq!(grid.render(&sample.source, sample.size).unwrap(), text);
+  }
+
+  #[test]
+  fn errors_report_their_location() {
+    assert!(matches!(
+      terrain().parse::<2>("~~\n~x"),
+      Err(Error::UnknownToken {
+        line: 2,
+        column: 2,
+        ..
+      })
+    ));
+    assert!(matches!(
+      terrain().parse::<2>("~~~\n~~"),
+      Err(Error::RaggedRow {
+        line: 2,
+        expected: 2,
+        found: 1
+      })
+    ));
+  }
+
+  #[test]
+  fn empty_tokens_are_ignored() {
+    let grid = terrain().with_token("", Terrain::Sand);
+
+    assert!(matches!(
+      grid.parse::<2>("~x"),
+      Err(Error::UnknownToken { column: 2, .. })
+    ));
+    assert_eq!(grid.render(&[Terrain::Sand], [1, 1].into()).unwrap(), "~");
+  }
+
+  #[test]
+  fn rendering_checks_the_size() {
+    assert!(matches!(
+      terrain().render(&[Terrain::Sand, Terrain::Water], [3, 1].into()),
+      Err(Error::SizeMismatch {
+        expected: 3,
+        found: 2
+      })
+    ));
+  }
+}
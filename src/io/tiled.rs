@@ -0,0 +1,712 @@
+//! Interoperability with the Tiled map editor.
+//!
+//! Tilesets (TSX) become [`Rules`] keyed by local tile id, either through per-tile properties named
+//! `left`, `right`, `up` and `down` (with `socket` as a fallback for any missing direction) or through a Wang set.
+//! Maps (TMX) with CSV, XML or uncompressed base64 encoded layers become samples or pre-filled cells, and collapsed states
+//! are written back as maps. Compressed layers are rejected, save the map without compression to use them.
+//!
+//! Rows run from the top of the map downwards, so `Dim2d::Up` points towards the first row.
+//! Rules have no notion of flipped tiles, so flipped cells are rejected when sampling or pre-filling and are available
+//! through [`TiledMap::flips`]
+
+use crate::{
+  Dimension, DirectionalConstraint, Observer, Rule, RuleBuilder, Rules, Size, Socket, State,
+  StateBuilder,
+  prebuilt::{Dim2d, auto::Sample},
+};
+use roxmltree::{Document, Node};
+use std::{
+  collections::{BTreeMap, HashMap},
+  fmt::Write,
+  path::Path,
+  str::FromStr,
+};
+use strum::IntoEnumIterator;
+
+/// Bits of a GID used by Tiled to mark flipped or rotated tiles
+const FLAGS: u32 = 0xF000_0000;
+const FLIPPED_HORIZONTALLY: u32 = 0x8000_0000;
+const FLIPPED_VERTICALLY: u32 = 0x4000_0000;
+const FLIPPED_DIAGONALLY: u32 = 0x2000_0000;
+
+/// Tile properties that declare sockets, other properties are ignored by [`Tileset::property_rules`]
+const SOCKET_PROPERTIES: [&str; 5] = ["socket", "left", "right", "up", "down"];
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+  #[error(transparent)]
+  Xml(#[from] roxmltree::Error),
+  #[error(transparent)]
+  Io(#[from] std::io::Error),
+  #[error("<{element}> is missing the attribute {attribute}")]
+  MissingAttribute { element: String, attribute: String },
+  #[error("Invalid value {0:?}")]
+  InvalidValue(String),
+  #[error("Unsupported layer encoding {0:?}")]
+  UnsupportedEncoding(String),
+  #[error("Unsupported layer compression {0:?}, save the map without compression")]
+  UnsupportedCompression(String),
+  #[error("Tile {tile} has no socket facing {direction:?}")]
+  MissingSocket { tile: u32, direction: Dim2d },
+  #[error("No layer named {0:?}")]
+  UnknownLayer(String),
+  #[error("No wang set named {0:?}")]
+  UnknownWangSet(String),
+  #[error("Cell {0:?} does not contain a tile from the tileset")]
+  EmptyCell([usize; 2]),
+  #[error("Cell {0:?} contains a flipped tile")]
+  FlippedCell([usize; 2]),
+  #[error("Layer has {found} cells but the map is {expected} cells large")]
+  SizeMismatch { expected: usize, found: usize },
+}
+
+/// Which parts of a tile the colors of a Wang set are assigned to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WangKind {
+  Edge,
+  Corner,
+  Mixed,
+}
+
+/// A Wang set, with the colors of every tile in the order Tiled stores them:
+/// top, top right, right, bottom right, bottom, bottom left, left, top left
+#[derive(Debug, Clone)]
+pub struct WangSet {
+  pub name: String,
+  pub kind: WangKind,
+  pub tiles: BTreeMap<u32, [u32; 8]>,
+}
+
+impl WangSet {
+  /// The colors along the side of a tile, ordered left to right or top to bottom so touching sides are equal
+  fn side(&self, colors: &[u32; 8], dir: Dim2d) -> String {
+    let [start, middle, end] = match dir {
+      Dim2d::Up => [7, 0, 1],
+      Dim2d::Down => [5, 4, 3],
+      Dim2d::Left => [7, 6, 5],
+      Dim2d::Right => [1, 2, 3],
+    };
+
+    let indices = match self.kind {
+      WangKind::Edge => vec![middle],
+      WangKind::Corner => vec![start, end],
+      WangKind::Mixed => vec![start, middle, end],
+    };
+
+    itertools::join(indices.into_iter().map(|i| colors[i]), ",")
+  }
+}
+
+/// A TSX tileset
+#[derive(Debug, Clone)]
+pub struct Tileset {
+  pub name: String,
+  pub tile_size: [u32; 2],
+  pub tile_count: u32,
+  pub columns: u32,
+  pub image: Option<String>,
+  /// Custom properties of every tile that declares any
+  pub properties: BTreeMap<u32, HashMap<String, String>>,
+  pub wang_sets: Vec<WangSet>,
+}
+
+impl Tileset {
+  pub fn parse(text: &str) -> Result<Self, Error> {
+    let document = Document::parse(text)?;
+    let root = document.root_element();
+
+    let mut properties = BTreeMap::new();
+    for tile in children(root, "tile") {
+      let values = children(tile, "properties")
+        .flat_map(|node| children(node, "property"))
+        .map(|property| {
+          Ok((
+            attr::<String>(property, "name")?,
+            property.attribute("value").unwrap_or_default().to_string(),
+          ))
+        })
+        .collect::<Result<HashMap<_, _>, Error>>()?;
+
+      if !values.is_empty() {
+        properties.insert(attr(tile, "id")?, values);
+      }
+    }
+
+    let mut wang_sets = Vec::new();
+    for set in children(root, "wangsets").flat_map(|node| children(node, "wangset")) {
+      let kind = match set.attribute("type").unwrap_or("mixed") {
+        "edge" => WangKind::Edge,
+        "corner" => WangKind::Corner,
+        "mixed" => WangKind::Mixed,
+        other => return Err(Error::InvalidValue(other.to_string())),
+      };
+
+      let mut tiles = BTreeMap::new();
+      for tile in children(set, "wangtile") {
+        let ids = attr::<String>(tile, "wangid")?
+          .split(',')
+          .map(|id| parse(id.trim()))
+          .collect::<Result<Vec<u32>, _>>()?;
+        let colors = <[u32; 8]>::try_from(ids)
+          .map_err(|ids| Error::InvalidValue(itertools::join(ids, ",")))?;
+        tiles.insert(attr(tile, "tileid")?, colors);
+      }
+
+      wang_sets.push(WangSet {
+        name: attr(set, "name")?,
+        kind,
+        tiles,
+      });
+    }
+
+    Ok(Self {
+      name: attr(root, "name")?,
+      tile_size: [attr(root, "tilewidth")?, attr(root, "tileheight")?],
+      tile_count: attr(root, "tilecount")?,
+      columns: attr(root, "columns")?,
+      image: children(root, "image")
+        .next()
+        .and_then(|image| image.attribute("source"))
+        .map(String::from),
+      properties,
+      wang_sets,
+    })
+  }
+
+  pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+    Self::parse(&std::fs::read_to_string(path)?)
+  }
+
+  /// Rules for every tile with socket properties, using the `left`, `right`, `up` and `down` properties as sockets,
+  /// or `socket` for any direction that is missing
+  pub fn property_rules(&self) -> Result<Rules<u32, Dim2d, String>, Error> {
+    let mut builder = RuleBuilder::default();
+
+    let tiles = self.properties.iter().filter(|(_, properties)| {
+      SOCKET_PROPERTIES
+        .iter()
+        .any(|name| properties.contains_key(*name))
+    });
+
+    for (tile, properties) in tiles {
+      let rule = Dim2d::iter()
+        .map(|dir| {
+          let name = format!("{dir:?}").to_lowercase();
+          properties
+            .get(&name)
+            .or_else(|| properties.get("socket"))
+            .map(|socket| (dir, socket.clone()))
+            .ok_or(Error::MissingSocket {
+              tile: *tile,
+              direction: dir,
+            })
+        })
+        .collect::<Result<Rule<Dim2d, String>, Error>>()?;
+
+      builder.add_rule(*tile, rule);
+    }
+
+    Ok(builder.into())
+  }
+
+  /// Rules for every tile within the Wang set, where each socket is the colors along that side of the tile.
+  /// Touching sides have identical sockets, so the rules are meant for a [`UnaryConstraint`](crate::prebuilt::constraints::UnaryConstraint)
+  pub fn wang_rules(&self, name: &str) -> Result<Rules<u32, Dim2d, String>, Error> {
+    let set = self
+      .wang_sets
+      .iter()
+      .find(|set| set.name == name)
+      .ok_or_else(|| Error::UnknownWangSet(name.to_string()))?;
+
+    Ok(
+      set
+        .tiles
+        .iter()
+        .map(|(tile, colors)| (*tile, Rule::from_fn(|dir| set.side(colors, dir))))
+        .collect::<RuleBuilder<_, _, _>>()
+        .into(),
+    )
+  }
+}
+
+/// A reference from a map to a tileset, the tiles of the tileset start at the first GID
+#[derive(Debug, Clone)]
+pub struct TilesetRef {
+  pub first_gid: u32,
+  pub source: String,
+}
+
+/// A single tile layer of a map, containing the GID of every cell with 0 being empty, including the flip flags
+#[derive(Debug, Clone)]
+pub struct TileLayer {
+  pub name: String,
+  pub gids: Vec<u32>,
+}
+
+/// A TMX map
+#[derive(Debug, Clone)]
+pub struct TiledMap {
+  pub size: [usize; 2],
+  pub tile_size: [u32; 2],
+  pub tilesets: Vec<TilesetRef>,
+  pub layers: Vec<TileLayer>,
+}
+
+impl TiledMap {
+  pub fn parse(text: &str) -> Result<Self, Error> {
+    let document = Document::parse(text)?;
+    let root = document.root_element();
+    let size = [attr(root, "width")?, attr(root, "height")?];
+
+    let tilesets = children(root, "tileset")
+      .map(|tileset| {
+        Ok(TilesetRef {
+          first_gid: attr(tileset, "firstgid")?,
+          source: tileset.attribute("source").unwrap_or_default().to_string(),
+        })
+      })
+      .collect::<Result<Vec<_>, Error>>()?;
+
+    let layers = children(root, "layer")
+      .map(|layer| {
+        let data = children(layer, "data")
+          .next()
+          .ok_or(Error::MissingAttribute {
+            element: "layer".to_string(),
+            attribute: "data".to_string(),
+          })?;
+
+        if let Some(compression) = data.attribute("compression") {
+          return Err(Error::UnsupportedCompression(compression.to_string()));
+        }
+
+        let gids = match data.attribute("encoding") {
+          Some("base64") => decode_base64(data.text().unwrap_or_default())?
+            .chunks(4)
+            .map(|gid| {
+              <[u8; 4]>::try_from(gid)
+                .map(u32::from_le_bytes)
+                .map_err(|_| Error::InvalidValue(format!("{gid:?}")))
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+          Some("csv") => data
+            .text()
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|gid| !gid.is_empty())
+            .map(parse::<u32>)
+            .collect::<Result<Vec<_>, _>>()?,
+          None => children(data, "tile")
+            .map(|tile| tile.attribute("gid").map(parse).unwrap_or(Ok(0)))
+            .collect::<Result<Vec<_>, _>>()?,
+          Some(other) => return Err(Error::UnsupportedEncoding(other.to_string())),
+        };
+
+        if gids.len() != size[0] * size[1] {
+          return Err(Error::SizeMismatch {
+            expected: size[0] * size[1],
+            found: gids.len(),
+          });
+        }
+
+        Ok(TileLayer {
+          name: layer.attribute("name").unwrap_or_default().to_string(),
+          gids,
+        })
+      })
+      .collect::<Result<Vec<_>, Error>>()?;
+
+    Ok(Self {
+      size,
+      tile_size: [attr(root, "tilewidth")?, attr(root, "tileheight")?],
+      tilesets,
+      layers,
+    })
+  }
+
+  pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+    Self::parse(&std::fs::read_to_string(path)?)
+  }
+
+  pub fn layer(&self, name: &str) -> Result<&TileLayer, Error> {
+    self
+      .layers
+      .iter()
+      .find(|layer| layer.name == name)
+      .ok_or_else(|| Error::UnknownLayer(name.to_string()))
+  }
+
+  /// How every cell of the layer is flipped
+  pub fn flips(&self, layer: &str) -> Result<Vec<Flip>, Error> {
+    Ok(self.layer(layer)?.gids.iter().copied().map(Flip::of).collect())
+  }
+
+  /// The local tile ids of the layer for the tileset starting at the first GID, cells without a tile of the tileset are `None`.
+  /// Flipped tiles are returned as their unflipped id, see [`TiledMap::flips`]
+  pub fn tiles(&self, layer: &str, first_gid: u32) -> Result<Vec<Option<u32>>, Error> {
+    let last_gid = self
+      .tilesets
+      .iter()
+      .map(|tileset| tileset.first_gid)
+      .filter(|gid| *gid > first_gid)
+      .min()
+      .unwrap_or(u32::MAX);
+
+    Ok(
+      self
+        .layer(layer)?
+        .gids
+        .iter()
+        .map(|gid| gid & !FLAGS)
+        .map(|gid| (gid >= first_gid && gid < last_gid).then(|| gid - first_gid))
+        .collect(),
+    )
+  }
+
+  /// The layer as a sample of local tile ids, every cell must contain an unflipped tile of the tileset
+  pub fn sample(&self, layer: &str, first_gid: u32) -> Result<Sample<u32, 2>, Error> {
+    let source = self
+      .unflipped_tiles(layer, first_gid)?
+      .into_iter()
+      .enumerate()
+      .map(|(i, tile)| tile.ok_or(Error::EmptyCell(self.position(i))))
+      .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Sample::new(source, self.size))
+  }
+
+  /// Inserts every tile of the layer into the builder, leaving empty cells to be generated. Flipped tiles are rejected
+  pub fn prefill<'b, O, C, S>(
+    &self,
+    layer: &str,
+    first_gid: u32,
+    builder: &'b mut StateBuilder<O, C, u32, Dim2d, S, 2>,
+  ) -> Result<&'b mut StateBuilder<O, C, u32, Dim2d, S, 2>, Error>
+  where
+    O: Observer<u32>,
+    C: DirectionalConstraint<u32, Dim2d, S>,
+    S: Socket,
+  {
+    for (i, tile) in self.unflipped_tiles(layer, first_gid)?.into_iter().enumerate() {
+      if let Some(tile) = tile {
+        builder.insert(self.position(i), tile);
+      }
+    }
+
+    Ok(builder)
+  }
+
+  fn unflipped_tiles(&self, layer: &str, first_gid: u32) -> Result<Vec<Option<u32>>, Error> {
+    let tiles = self.tiles(layer, first_gid)?;
+
+    for (i, (tile, flip)) in tiles.iter().zip(self.flips(layer)?).enumerate() {
+      if tile.is_some() && flip != Flip::default() {
+        return Err(Error::FlippedCell(self.position(i)));
+      }
+    }
+
+    Ok(tiles)
+  }
+
+  fn position(&self, index: usize) -> [usize; 2] {
+    [index % self.size[0], index / self.size[0]]
+  }
+}
+
+/// How a cell is flipped, taken from the flags of its GID.
+/// A diagonal flip swaps the x and y axes and is applied before the other two, which is how Tiled stores rotations
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Flip {
+  pub horizontal: bool,
+  pub vertical: bool,
+  pub diagonal: bool,
+}
+
+impl Flip {
+  pub fn of(gid: u32) -> Self {
+    Self {
+      horizontal: gid & FLIPPED_HORIZONTALLY != 0,
+      vertical: gid & FLIPPED_VERTICALLY != 0,
+      diagonal: gid & FLIPPED_DIAGONALLY != 0,
+    }
+  }
+}
+
+/// Writes local tile ids as a single layer TMX map using the tileset, leaving cells that are `None` empty
+pub fn write_map(
+  cells: &[Option<u32>],
+  size: Size<2>,
+  tile_size: [u32; 2],
+  tileset: &TilesetRef,
+) -> String {
+  let [width, height] = [size[0], size[1]];
+  let mut output = String::new();
+
+  let _ = writeln!(output, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+  let _ = writeln!(
+    output,
+    r#"<map version="1.10" orientation="orthogonal" renderorder="right-down" width="{width}" height="{height}" tilewidth="{}" tileheight="{}" infinite="0" nextlayerid="2" nextobjectid="1">"#,
+    tile_size[0], tile_size[1]
+  );
+  let _ = writeln!(
+    output,
+    r#" <tileset firstgid="{}" source="{}"/>"#,
+    tileset.first_gid,
+    escape(&tileset.source)
+  );
+  let _ = writeln!(
+    output,
+    r#" <layer id="1" name="wfc" width="{width}" height="{height}">"#
+  );
+  let _ = writeln!(output, r#"  <data encoding="csv">"#);
+
+  let rows = cells.chunks(width).map(|row| {
+    itertools::join(
+      row.iter().map(|cell| {
+        cell
+          .map(|tile| tile + tileset.first_gid)
+          .unwrap_or_default()
+      }),
+      ",",
+    )
+  });
+  let _ = writeln!(output, "{}", itertools::join(rows, ",\n"));
+
+  let _ = writeln!(output, "</data>");
+  let _ = writeln!(output, " </layer>");
+  let _ = writeln!(output, "</map>");
+
+  output
+}
+
+/// Writes the state as a single layer TMX map, leaving cells that have not collapsed empty
+pub fn write_state<O, C, D, S>(
+  state: &State<O, C, u32, D, S, 2>,
+  tile_size: [u32; 2],
+  tileset: &TilesetRef,
+) -> String
+where
+  O: Observer<u32>,
+  C: DirectionalConstraint<u32, D, S>,
+  D: Dimension,
+  S: Socket,
+{
+  write_map(&state.data_raw(), *state.size(), tile_size, tileset)
+}
+
+fn children<'a, 'i>(node: Node<'a, 'i>, name: &'static str) -> impl Iterator<Item = Node<'a, 'i>> {
+  node
+    .children()
+    .filter(move |child| child.has_tag_name(name))
+}
+
+fn attr<T: FromStr>(node: Node, name: &str) -> Result<T, Error> {
+  let value = node
+    .attribute(name)
+    .ok_or_else(|| Error::MissingAttribute {
+      element: node.tag_name().name().to_string(),
+      attribute: name.to_string(),
+    })?;
+
+  parse(value)
+}
+
+fn parse<T: FromStr>(value: &str) -> Result<T, Error> {
+  value
+    .parse()
+    .map_err(|_| Error::InvalidValue(value.to_string()))
+}
+
+/// Decodes standard base64, ignoring the whitespace Tiled surrounds layer data with
+fn decode_base64(text: &str) -> Result<Vec<u8>, Error> {
+  let invalid = || Error::InvalidValue(text.trim().to_string());
+
+  let values = text
+    .bytes()
+    .filter(|byte| !byte.is_ascii_whitespace())
+    .take_while(|byte| *byte != b'=')
+    .map(|byte| match byte {
+      b'A'..=b'Z' => Ok(byte - b'A'),
+      b'a'..=b'z' => Ok(byte - b'a' + 26),
+      b'0'..=b'9' => Ok(byte - b'0' + 52),
+      b'+' => Ok(62),
+      b'/' => Ok(63),
+      _ => Err(invalid()),
+    })
+    .collect::<Result<Vec<u8>, _>>()?;
+
+  if values.len() % 4 == 1 {
+    return Err(invalid());
+  }
+
+  Ok(
+    values
+      .chunks(4)
+      .flat_map(|chunk| {
+        let bits = chunk
+          .iter()
+          .enumerate()
+          .fold(0u32, |bits, (i, value)| bits | (*value as u32) << (18 - 6 * i));
+        let bytes = bits.to_be_bytes();
+        bytes[1..chunk.len()].to_vec()
+      })
+      .collect(),
+  )
+}
+
+fn escape(value: &str) -> String {
+  value
+    .replace('&', "&amp;")
+    .replace('"', "&quot;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{Error, Flip, TiledMap, Tileset, TilesetRef, write_map};
+  use crate::{
+    StateBuilder,
+    prebuilt::{Dim2d, constraints::UnaryConstraint, processing::RandomObserver},
+  };
+
+  const TSX: &str = r##"<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.10" name="roads" tilewidth="16" tileheight="16" tilecount="3" columns="3">
+ <image source="roads.png" width="48" height="16"/>
+ <tile id="0">
+  <properties>
+   <property name="socket" value="grass"/>
+  </properties>
+ </tile>
+ <tile id="2">
+  <properties>
+   <property name="collision" value="true"/>
+  </properties>
+ </tile>
+ <tile id="1">
+  <properties>
+   <property name="collision" value="false"/>
+   <property name="socket" value="grass"/>
+   <property name="left" value="road"/>
+   <property name="right" value="road"/>
+  </properties>
+ </tile>
+ <wangsets>
+  <wangset name="terrain" type="corner" tile="-1">
+   <wangcolor name="grass" color="#00ff00" tile="-1" probability="1"/>
+   <wangcolor name="water" color="#0000ff" tile="-1" probability="1"/>
+   <wangtile tileid="0" wangid="0,1,0,1,0,1,0,1"/>
+   <wangtile tileid="2" wangid="0,1,0,2,0,2,0,1"/>
+  </wangset>
+ </wangsets>
+</tileset>
+"##;
+
+  const TMX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="orthogonal" renderorder="right-down" width="3" height="2" tilewidth="16" tileheight="16" infinite="0">
+ <tileset firstgid="1" source="roads.tsx"/>
+ <layer id="1" name="ground" width="3" height="2">
+  <data encoding="csv">
+1,2,1,
+1,0,2147483650
+</data>
+ </layer>
+</map>
+"#;
+
+  #[test]
+  fn tilesets_become_rules() {
+    let tileset = Tileset::parse(TSX).unwrap();
+
+    assert_eq!(tileset.tile_size, [16, 16]);
+    assert_eq!(tileset.image.as_deref(), Some("roads.png"));
+
+    let rules = tileset.property_rules().unwrap();
+    let road = rules.rule_for(&1).unwrap();
+    assert_eq!(road.socket_for(&Dim2d::Left).unwrap(), "road");
+    assert_eq!(road.socket_for(&Dim2d::Up).unwrap(), "grass");
+    assert!(rules.rule_for(&2).is_none());
+
+    let rules = tileset.wang_rules("terrain").unwrap();
+    let shore = rules.rule_for(&2).unwrap();
+    assert_eq!(shore.socket_for(&Dim2d::Up).unwrap(), "1,1");
+    assert_eq!(shore.socket_for(&Dim2d::Down).unwrap(), "2,2");
+    assert_eq!(shore.socket_for(&Dim2d::Right).unwrap(), "1,2");
+    assert!(rules.rule_for(&1).is_none());
+  }
+
+  #[test]
+  fn maps_round_trip() {
+    let map = TiledMap::parse(TMX).unwrap();
+    let tiles = map.tiles("ground", 1).unwrap();
+
+    assert_eq!(
+      tiles,
+      vec![Some(0), Some(1), Some(0), Some(0), None, Some(1)]
+    );
+    assert!(map.sample("ground", 1).is_err());
+    assert_eq!(
+      map.flips("ground").unwrap()[5],
+      Flip {
+        horizontal: true,
+        ..Default::default()
+      }
+    );
+
+    let tileset = TilesetRef {
+      first_gid: 1,
+      source: "roads.tsx".to_string(),
+    };
+    let written =
+      TiledMap::parse(&write_map(&tiles, map.size.into(), map.tile_size, &tileset)).unwrap();
+
+    assert_eq!(written.tiles("wfc", 1).unwrap(), tiles);
+  }
+
+  #[test]
+  fn base64_layers_are_decoded() {
+    // the gids 1, 2, 1, 1, 0 and 2 flipped horizontally as little endian u32s
+    let data = r#"<data encoding="base64">
+   AQAAAAIAAAABAAAAAQAAAAAAAAACAACA
+  </data>"#;
+    let text = TMX.replace(
+      "<data encoding=\"csv\">\n1,2,1,\n1,0,2147483650\n</data>",
+      data,
+    );
+    assert!(!text.contains("csv"));
+
+    let map = TiledMap::parse(&text).unwrap();
+    assert_eq!(map.layers[0].gids, TiledMap::parse(TMX).unwrap().layers[0].gids);
+
+    let compressed = TMX.replace(r#"encoding="csv""#, r#"encoding="base64" compression="zlib""#);
+    assert!(matches!(
+      TiledMap::parse(&compressed),
+      Err(Error::UnsupportedCompression(_))
+    ));
+  }
+
+  #[test]
+  fn flipped_tiles_are_rejected() {
+    let map = TiledMap::parse(&TMX.replace("1,0,2147483650", "1,1,2147483650")).unwrap();
+    assert!(matches!(
+      map.sample("ground", 1),
+      Err(Error::FlippedCell([2, 1]))
+    ));
+  }
+
+  #[test]
+  fn maps_prefill_states() {
+    let rules = Tileset::parse(TSX).unwrap().wang_rules("terrain").unwrap();
+    let map = TiledMap::parse(&TMX.replace("1,2,1,\n1,0,2147483650", "1,0,1,\n0,0,0")).unwrap();
+
+    let mut builder =
+      StateBuilder::new([3, 2], RandomObserver::new(Some(1)), UnaryConstraint, rules);
+    map.prefill("ground", 1, &mut builder).unwrap();
+    let state = builder.build().unwrap();
+
+    assert_eq!(state.data_raw()[0], Some(0));
+    assert_eq!(state.data_raw()[2], Some(0));
+    assert_eq!(state.data_raw()[1], None);
+  }
+}
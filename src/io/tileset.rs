@@ -0,0 +1,575 @@
+//! Tile sets described in data rather than code.
+//!
+//! Each tile declares a socket used for every direction, and may override it for specific directions.
+//! A RON tile set could look like
+//!
+//! ```ron
+//! (
+//!   tiles: [
+//!     (variant: "grass", socket: "green", weight: 4.0),
+//!     (variant: "road", socket: "green", sockets: {Left: "road", Right: "road"}, limit: 12),
+//!     (variant: "bend", socket: "green", sockets: {Left: "road", Down: "road"}, symmetry: L),
+//!   ],
+//!   connections: [(from: "road", to: "road")],
+//!   wildcards: [],
+//! )
+//! ```
+//!
+//! Without any connections or wildcards, each socket connects only to itself.
+//!
+//! Tiles with a symmetry stand for every distinct orientation of themselves, which
+//! [`TileSet::expanded`] turns into separate tiles before generating.
+
+use crate::{
+  Dimension, Planar, Rule, RuleBuilder, Rules, Socket, Variant, Weight,
+  prebuilt::{constraints::MatrixConstraint, processing::LimitMod, shapes::WeightedShape},
+};
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+  #[error("Variant {variant} has no socket facing {direction}")]
+  MissingSocket { variant: String, direction: String },
+  #[error("Variant {variant} declares a symmetry, expand the tile set first")]
+  Unexpanded { variant: String },
+  #[cfg(feature = "ron")]
+  #[error(transparent)]
+  Ron(#[from] ron::Error),
+  #[cfg(feature = "ron")]
+  #[error(transparent)]
+  RonSpanned(#[from] ron::error::SpannedError),
+  #[cfg(feature = "json")]
+  #[error(transparent)]
+  Json(#[from] serde_json::Error),
+  #[cfg(feature = "toml")]
+  #[error(transparent)]
+  TomlDe(#[from] toml::de::Error),
+  #[cfg(feature = "toml")]
+  #[error(transparent)]
+  TomlSer(#[from] toml::ser::Error),
+}
+
+/// A complete description of a tile set, which can be turned into [`Rules`], a [`WeightedShape`],
+/// a [`LimitMod`] and a [`MatrixConstraint`]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "bevy", derive(bevy_reflect::TypePath))]
+pub struct TileSet<V: Variant, D: Dimension, S: Socket, W = f32> {
+  pub tiles: Vec<Tile<V, D, S, W>>,
+  /// Sockets that may connect to each other, only used by [`TileSet::constraint`]
+  #[serde(default = "Vec::new", skip_serializing_if = "Vec::is_empty")]
+  pub connections: Vec<SocketPair<S>>,
+  /// Sockets that connect to anything, only used by [`TileSet::constraint`]
+  #[serde(default = "Vec::new", skip_serializing_if = "Vec::is_empty")]
+  pub wildcards: Vec<S>,
+}
+
+/// A single variant within a tile set
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Tile<V: Variant, D: Dimension, S: Socket, W = f32> {
+  pub variant: V,
+  /// The socket used for any direction not within `sockets`
+  #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+  pub socket: Option<S>,
+  #[serde(default = "BTreeMap::new", skip_serializing_if = "BTreeMap::is_empty")]
+  pub sockets: BTreeMap<D, S>,
+  /// Defaults to 1 when absent
+  #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+  pub weight: Option<W>,
+  /// The maximum amount of times the variant may be selected
+  #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+  pub limit: Option<usize>,
+  /// Which orientations of the tile are distinct, the tile is used as declared when absent
+  #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+  pub symmetry: Option<Symmetry>,
+}
+
+/// The symmetry classes of the simple tiled model, named after letters that share the symmetry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Symmetry {
+  /// The same in every orientation
+  X,
+  /// Two orientations, such as a straight road
+  I,
+  /// Two orientations, such as a diagonal
+  Backslash,
+  /// Four rotations, such as a junction
+  T,
+  /// Four rotations, such as a bend
+  L,
+  /// Four rotations of both the tile and its reflection
+  F,
+}
+
+impl Symmetry {
+  /// The amount of distinct orientations
+  pub fn orientations(&self) -> usize {
+    match self {
+      Self::X => 1,
+      Self::I | Self::Backslash => 2,
+      Self::T | Self::L => 4,
+      Self::F => 8,
+    }
+  }
+}
+
+impl<V: Variant, D: Dimension, S: Socket, W> Tile<V, D, S, W> {
+  pub fn new(variant: V) -> Self {
+    Self {
+      variant,
+      socket: None,
+      sockets: Default::default(),
+      weight: None,
+      limit: None,
+      symmetry: None,
+    }
+  }
+
+  pub fn with_socket(mut self, socket: S) -> Self {
+    self.socket = Some(socket);
+    self
+  }
+
+  pub fn with_socket_for(mut self, dir: D, socket: S) -> Self {
+    self.sockets.insert(dir, socket);
+    self
+  }
+
+  pub fn with_weight(mut self, weight: W) -> Self {
+    self.weight = Some(weight);
+    self
+  }
+
+  pub fn with_limit(mut self, limit: usize) -> Self {
+    self.limit = Some(limit);
+    self
+  }
+
+  pub fn with_symmetry(mut self, symmetry: Symmetry) -> Self {
+    self.symmetry = Some(symmetry);
+    self
+  }
+
+  pub fn socket_for(&self, dir: &D) -> Option<&S> {
+    self.sockets.get(dir).or(self.socket.as_ref())
+  }
+
+  /// The tile reflected when the orientation is 4 or above, then rotated clockwise by the
+  /// orientation modulo 4 quarter turns, without a symmetry
+  pub fn oriented(&self, variant: V, orientation: usize) -> Self
+  where
+    D: Planar,
+    W: Clone,
+  {
+    let orient = |dir: &D| {
+      let dir = if orientation >= 4 {
+        dir.reflected()
+      } else {
+        *dir
+      };
+      (0..orientation % 4).fold(dir, |dir, _| dir.rotated())
+    };
+
+    Self {
+      variant,
+      socket: self.socket.clone(),
+      sockets: self
+        .sockets
+        .iter()
+        .map(|(dir, socket)| (orient(dir), socket.clone()))
+        .collect(),
+      weight: self.weight.clone(),
+      limit: self.limit,
+      symmetry: None,
+    }
+  }
+}
+
+/// Two sockets that may connect, in both directions unless marked as one way
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SocketPair<S> {
+  pub from: S,
+  pub to: S,
+  #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+  pub one_way: bool,
+}
+
+impl<V: Variant, D: Dimension, S: Socket, W> Default for TileSet<V, D, S, W> {
+  fn default() -> Self {
+    Self {
+      tiles: Default::default(),
+      connections: Default::default(),
+      wildcards: Default::default(),
+    }
+  }
+}
+
+impl<V: Variant, D: Dimension, S: Socket, W> TileSet<V, D, S, W> {
+  /// Describes existing rules, using a single socket for tiles that are the same in every direction
+  pub fn from_rules(rules: &Rules<V, D, S>) -> Self {
+    let mut variants = rules.variants().collect::<Vec<_>>();
+    variants.sort();
+
+    let tiles = variants
+      .into_iter()
+      .map(|variant| {
+        let mut tile = Tile::new(variant.clone());
+        let rule = rules.rule_for(variant).unwrap();
+        let mut sockets = D::iter().filter_map(|dir| rule.socket_for(&dir).map(|s| (dir, s)));
+
+        if let Some((_, first)) = sockets.next()
+          && rule.len() == D::COUNT
+          && sockets.all(|(_, socket)| socket == first)
+        {
+          tile.socket = Some(first.clone());
+        } else {
+          tile.sockets = rule.iter().map(|(d, s)| (*d, s.clone())).collect();
+        }
+
+        tile
+      })
+      .collect();
+
+    Self {
+      tiles,
+      ..Default::default()
+    }
+  }
+
+  pub fn add_tile(&mut self, tile: Tile<V, D, S, W>) -> &mut Self {
+    self.tiles.push(tile);
+    self
+  }
+
+  pub fn with_tile(mut self, tile: Tile<V, D, S, W>) -> Self {
+    self.add_tile(tile);
+    self
+  }
+
+  pub fn add_connection(&mut self, from: S, to: S, one_way: bool) -> &mut Self {
+    self.connections.push(SocketPair { from, to, one_way });
+    self
+  }
+
+  pub fn with_connection(mut self, from: S, to: S, one_way: bool) -> Self {
+    self.add_connection(from, to, one_way);
+    self
+  }
+
+  pub fn tile(&self, variant: &V) -> Option<&Tile<V, D, S, W>> {
+    self.tiles.iter().find(|tile| tile.variant == *variant)
+  }
+
+  /// Replaces every tile that declares a symmetry with a tile for each of its orientations.
+  /// The first orientation keeps the variant, the rest are named by `name` from the variant and
+  /// orientation. Weights and limits apply to each orientation separately
+  pub fn expanded(&self, mut name: impl FnMut(&V, usize) -> V) -> Self
+  where
+    D: Planar,
+    W: Clone,
+  {
+    let tiles = self
+      .tiles
+      .iter()
+      .flat_map(|tile| match tile.symmetry {
+        Some(symmetry) => (0..symmetry.orientations())
+          .map(|orientation| {
+            let variant = match orientation {
+              0 => tile.variant.clone(),
+              _ => name(&tile.variant, orientation),
+            };
+            tile.oriented(variant, orientation)
+          })
+          .collect(),
+        None => vec![tile.clone()],
+      })
+      .collect();
+
+    Self {
+      tiles,
+      connections: self.connections.clone(),
+      wildcards: self.wildcards.clone(),
+    }
+  }
+
+  /// The rules of every tile, failing if any tile is missing a socket in some direction or still
+  /// declares a symmetry
+  pub fn rules(&self) -> Result<Rules<V, D, S>, Error> {
+    let mut builder = RuleBuilder::default();
+
+    for tile in &self.tiles {
+      if tile.symmetry.is_some() {
+        return Err(Error::Unexpanded {
+          variant: format!("{:?}", tile.variant),
+        });
+      }
+
+      let rule = D::iter()
+        .map(|dir| {
+          tile
+            .socket_for(&dir)
+            .map(|socket| (dir, socket.clone()))
+            .ok_or_else(|| Error::MissingSocket {
+              variant: format!("{:?}", tile.variant),
+              direction: format!("{dir:?}"),
+            })
+        })
+        .collect::<Result<Rule<D, S>, Error>>()?;
+
+      builder.add_rule(tile.variant.clone(), rule);
+    }
+
+    Ok(builder.into())
+  }
+
+  /// The weight of every tile
+  pub fn weights(&self) -> WeightedShape<V, W>
+  where
+    W: Weight + From<u8>,
+  {
+    WeightedShape::new(
+      self
+        .tiles
+        .iter()
+        .map(|tile| (tile.variant.clone(), tile.weight.unwrap_or(W::from(1))))
+        .collect::<HashMap<_, _>>(),
+    )
+  }
+
+  /// The limit of every tile that declares one
+  pub fn limits(&self) -> LimitMod<V> {
+    LimitMod::new(
+      self
+        .tiles
+        .iter()
+        .filter_map(|tile| Some((tile.variant.clone(), tile.limit?)))
+        .collect::<HashMap<_, _>>(),
+    )
+  }
+
+  /// A constraint connecting the declared socket pairs and wildcards,
+  /// or every socket to itself when neither are declared
+  pub fn constraint(&self) -> MatrixConstraint<S> {
+    let mut constraint = MatrixConstraint::default();
+
+    if self.connections.is_empty() && self.wildcards.is_empty() {
+      for tile in &self.tiles {
+        for socket in tile.socket.iter().chain(tile.sockets.values()) {
+          constraint.add_pair(socket.clone(), socket.clone());
+        }
+      }
+      return constraint;
+    }
+
+    for pair in &self.connections {
+      if pair.one_way {
+        constraint.add_pair(pair.from.clone(), pair.to.clone());
+      } else {
+        constraint.add_symmetric_pair(pair.from.clone(), pair.to.clone());
+      }
+    }
+
+    for wildcard in &self.wildcards {
+      constraint.add_wildcard(wildcard.clone());
+    }
+
+    constraint
+  }
+}
+
+impl<V, D, S, W> TileSet<V, D, S, W>
+where
+  V: Variant + serde::Serialize + for<'de> serde::Deserialize<'de>,
+  D: Dimension + serde::Serialize + for<'de> serde::Deserialize<'de>,
+  S: Socket + serde::Serialize + for<'de> serde::Deserialize<'de>,
+  W: serde::Serialize + for<'de> serde::Deserialize<'de>,
+{
+  /// Parses RON with implicit `Some`, so optional fields do not need to be wrapped
+  #[cfg(feature = "ron")]
+  pub fn from_ron(text: &str) -> Result<Self, Error> {
+    Ok(
+      ron::Options::default()
+        .with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME)
+        .from_str(text)?,
+    )
+  }
+
+  #[cfg(feature = "ron")]
+  pub fn to_ron(&self) -> Result<String, Error> {
+    Ok(ron::ser::to_string_pretty(
+      self,
+      ron::ser::PrettyConfig::default().extensions(ron::extensions::Extensions::IMPLICIT_SOME),
+    )?)
+  }
+
+  #[cfg(feature = "json")]
+  pub fn from_json(text: &str) -> Result<Self, Error> {
+    Ok(serde_json::from_str(text)?)
+  }
+
+  #[cfg(feature = "json")]
+  pub fn to_json(&self) -> Result<String, Error> {
+    Ok(serde_json::to_string_pretty(self)?)
+  }
+
+  #[cfg(feature = "toml")]
+  pub fn from_toml(text: &str) -> Result<Self, Error> {
+    Ok(toml::from_str(text)?)
+  }
+
+  #[cfg(feature = "toml")]
+  pub fn to_toml(&self) -> Result<String, Error> {
+    Ok(toml::to_string_pretty(self)?)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{Symmetry, Tile, TileSet};
+  use crate::{Constraint, prebuilt::Dim2d, strum::VariantArray};
+  use std::collections::HashSet;
+
+  type Roads = TileSet<String, Dim2d, String>;
+
+  fn roads() -> Roads {
+    TileSet::default()
+      .with_tile(
+        Tile::new("grass".to_string())
+          .with_socket("green".to_string())
+          .with_weight(4.0),
+      )
+      .with_tile(
+        Tile::new("road".to_string())
+          .with_socket("green".to_string())
+          .with_socket_for(Dim2d::Left, "road".to_string())
+          .with_socket_for(Dim2d::Right, "road".to_string())
+          .with_limit(12),
+      )
+      .with_connection("road".to_string(), "road".to_string(), false)
+      .with_connection("green".to_string(), "green".to_string(), false)
+  }
+
+  #[test]
+  fn tile_sets_produce_rules_weights_and_limits() {
+    let tiles = roads();
+
+    let rules = tiles.rules().unwrap();
+    let road = rules.rule_for(&"road".to_string()).unwrap();
+    assert_eq!(road.socket_for(&Dim2d::Left).unwrap(), "road");
+    assert_eq!(road.socket_for(&Dim2d::Up).unwrap(), "green");
+
+    assert_eq!(tiles.weights()[&"grass".to_string()], 4.0);
+    assert_eq!(tiles.weights()[&"road".to_string()], 1.0);
+    assert_eq!(tiles.limits()[&"road".to_string()], 12);
+    assert!(!tiles.limits().contains_key(&"grass".to_string()));
+
+    let constraint = tiles.constraint();
+    assert!(constraint.check(&"road".to_string(), &HashSet::from(["road".to_string()])));
+    assert!(!constraint.check(&"road".to_string(), &HashSet::from(["green".to_string()])));
+
+    let mut unconnected = tiles.clone();
+    unconnected.connections.clear();
+    let constraint = unconnected.constraint();
+    assert!(constraint.check(&"green".to_string(), &HashSet::from(["green".to_string()])));
+    assert!(!constraint.check(&"green".to_string(), &HashSet::from(["road".to_string()])));
+
+    let missing = Roads::default()
+      .with_tile(Tile::new("broken".to_string()).with_socket_for(Dim2d::Left, "x".to_string()));
+    assert!(missing.rules().is_err());
+  }
+
+  #[test]
+  fn rules_are_described() {
+    let tiles = roads();
+    let described = Roads::from_rules(&tiles.rules().unwrap());
+
+    assert_eq!(
+      described
+        .tile(&"grass".to_string())
+        .unwrap()
+        .socket
+        .as_deref(),
+      Some("green")
+    );
+    assert_eq!(described.rules().unwrap(), tiles.rules().unwrap());
+  }
+
+  #[test]
+  fn symmetric_tiles_are_expanded() {
+    let tiles = Roads::default()
+      .with_tile(Tile::new("grass".to_string()).with_socket("green".to_string()))
+      .with_tile(
+        Tile::new("bend".to_string())
+          .with_socket("green".to_string())
+          .with_socket_for(Dim2d::Left, "road".to_string())
+          .with_socket_for(Dim2d::Down, "road".to_string())
+          .with_symmetry(Symmetry::L),
+      )
+      .with_tile(
+        Tile::new("ramp".to_string())
+          .with_socket("green".to_string())
+          .with_socket_for(Dim2d::Left, "low".to_string())
+          .with_socket_for(Dim2d::Up, "high".to_string())
+          .with_symmetry(Symmetry::F),
+      );
+    assert!(tiles.rules().is_err());
+
+    #[cfg(feature = "ron")]
+    assert_eq!(Roads::from_ron(&tiles.to_ron().unwrap()).unwrap(), tiles);
+    #[cfg(feature = "json")]
+    assert_eq!(Roads::from_json(&tiles.to_json().unwrap()).unwrap(), tiles);
+    #[cfg(feature = "toml")]
+    assert_eq!(Roads::from_toml(&tiles.to_toml().unwrap()).unwrap(), tiles);
+
+    let expanded = tiles.expanded(|variant, orientation| format!("{variant}{orientation}"));
+    assert_eq!(expanded.tiles.len(), 1 + 4 + 8);
+    assert!(expanded.tiles.iter().all(|tile| tile.symmetry.is_none()));
+
+    let rules = expanded.rules().unwrap();
+    let bend = |variant: &str| {
+      let rule = rules.rule_for(&variant.to_string()).unwrap();
+      Dim2d::VARIANTS
+        .iter()
+        .filter(|dir| rule.socket_for(dir).unwrap() == "road")
+        .copied()
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(bend("bend"), [Dim2d::Left, Dim2d::Down]);
+    assert_eq!(bend("bend1"), [Dim2d::Left, Dim2d::Up]);
+    assert_eq!(bend("bend2"), [Dim2d::Right, Dim2d::Up]);
+    assert_eq!(bend("bend3"), [Dim2d::Right, Dim2d::Down]);
+
+    let reflected = expanded.tile(&"ramp4".to_string()).unwrap();
+    assert_eq!(reflected.socket_for(&Dim2d::Right).unwrap(), "low");
+    assert_eq!(reflected.socket_for(&Dim2d::Up).unwrap(), "high");
+    let turned = expanded.tile(&"ramp5".to_string()).unwrap();
+    assert_eq!(turned.socket_for(&Dim2d::Down).unwrap(), "low");
+    assert_eq!(turned.socket_for(&Dim2d::Right).unwrap(), "high");
+  }
+
+  #[cfg(feature = "ron")]
+  #[test]
+  fn ron_round_trips() {
+    let text = roads().to_ron().unwrap();
+    let parsed = Roads::from_ron(&text).unwrap();
+    assert_eq!(parsed, roads());
+    assert_eq!(parsed.to_ron().unwrap(), text);
+  }
+
+  #[cfg(feature = "json")]
+  #[test]
+  fn json_round_trips() {
+    let text = roads().to_json().unwrap();
+    let parsed = Roads::from_json(&text).unwrap();
+    assert_eq!(parsed, roads());
+    assert_eq!(parsed.to_json().unwrap(), text);
+  }
+
+  #[cfg(feature = "toml")]
+  #[test]
+  fn toml_round_trips() {
+    let text = roads().to_toml().unwrap();
+    let parsed = Roads::from_toml(&text).unwrap();
+    assert_eq!(parsed, roads());
+    assert_eq!(parsed.to_toml().unwrap(), text);
+  }
+}
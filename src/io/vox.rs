@@ -0,0 +1,475 @@
+//! MagicaVoxel `.vox` models for 3D outputs and samples.
+//!
+//! MagicaVoxel is Z-up while grids here are ordered x, y, z with `Dim3d::Up` pointing towards y = 0.
+//! Grid x becomes model x, grid z becomes model y, and grid y is flipped to become model z, so `Dim3d::Up` points up in the editor.
+//! A model may be at most 256 voxels along each axis and use at most 255 colors.
+//! Models without a palette use MagicaVoxel's default palette
+
+use crate::{
+  Dimension, DirectionalConstraint, Observer, Size, Socket, State, Variant, prebuilt::auto::Sample,
+};
+use std::{
+  collections::HashMap,
+  io::{Read, Write},
+  path::Path,
+};
+
+/// An RGBA color, one byte per channel
+pub type Color = [u8; 4];
+
+const VERSION: i32 = 150;
+const MAX_SIZE: usize = 256;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+  #[error(transparent)]
+  Io(#[from] std::io::Error),
+  #[error("Not a vox file")]
+  InvalidHeader,
+  #[error("The model has no {0} chunk")]
+  MissingChunk(&'static str),
+  #[error("Models may be at most 256 voxels along each axis, found {0:?}")]
+  TooLarge([usize; 3]),
+  #[error("Models may use at most 255 colors, found {0}")]
+  TooManyColors(usize),
+  #[error("Voxel {0:?} is outside of the model")]
+  OutOfBounds([u8; 3]),
+  #[error("Expected {expected} values for the size but {found} were given")]
+  SizeMismatch { expected: usize, found: usize },
+}
+
+/// A grid of voxels in grid axis order, empty voxels are `None`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Voxels {
+  pub size: [usize; 3],
+  pub voxels: Vec<Option<Color>>,
+}
+
+impl Voxels {
+  /// One voxel per cell, cells without a color or that have not collapsed are left empty
+  pub fn from_cells<V>(
+    cells: &[Option<V>],
+    size: Size<3>,
+    color: impl Fn(&V) -> Option<Color>,
+  ) -> Result<Self, Error> {
+    check_len(cells, size.len())?;
+
+    Ok(Self {
+      size: [size[0], size[1], size[2]],
+      voxels: cells
+        .iter()
+        .map(|cell| cell.as_ref().and_then(&color))
+        .collect(),
+    })
+  }
+
+  /// Expands every cell into a block of voxels. Each model is laid out in grid axis order and must fill the whole block,
+  /// cells without a model are left empty
+  pub fn from_blocks<'m, V>(
+    cells: &[Option<V>],
+    size: Size<3>,
+    block: [usize; 3],
+    model: impl Fn(&V) -> Option<&'m [Option<Color>]>,
+  ) -> Result<Self, Error> {
+    check_len(cells, size.len())?;
+
+    let out_size = [size[0] * block[0], size[1] * block[1], size[2] * block[2]];
+    let mut voxels = vec![None; out_size.iter().product()];
+
+    for (i, cell) in cells.iter().enumerate() {
+      let Some(model) = cell.as_ref().and_then(&model) else {
+        continue;
+      };
+      check_len(model, block.iter().product())?;
+
+      let cell_pos = [i % size[0], i / size[0] % size[1], i / (size[0] * size[1])];
+
+      for (j, voxel) in model.iter().enumerate() {
+        let local = [
+          j % block[0],
+          j / block[0] % block[1],
+          j / (block[0] * block[1]),
+        ];
+        let [x, y, z] = std::array::from_fn(|a| cell_pos[a] * block[a] + local[a]);
+        voxels[x + y * out_size[0] + z * out_size[0] * out_size[1]] = *voxel;
+      }
+    }
+
+    Ok(Self {
+      size: out_size,
+      voxels,
+    })
+  }
+
+  /// One voxel per cell of the state
+  pub fn from_state<O, C, V, D, S>(
+    state: &State<O, C, V, D, S, 3>,
+    color: impl Fn(&V) -> Option<Color>,
+  ) -> Result<Self, Error>
+  where
+    O: Observer<V>,
+    C: DirectionalConstraint<V, D, S>,
+    V: Variant,
+    D: Dimension,
+    S: Socket,
+  {
+    Self::from_cells(&state.data_raw(), *state.size(), color)
+  }
+
+  /// The voxels as a sample, with empty voxels as `None`
+  pub fn sample(&self) -> Sample<Option<Color>, 3> {
+    Sample::new(self.voxels.clone(), self.size)
+  }
+
+  pub fn write(&self, mut writer: impl Write) -> Result<(), Error> {
+    if self.size.iter().any(|axis| *axis > MAX_SIZE) {
+      return Err(Error::TooLarge(self.size));
+    }
+
+    let mut palette = Vec::<Color>::new();
+    let mut indices = HashMap::<Color, u8>::new();
+    let mut xyzi = Vec::new();
+
+    for (i, voxel) in self.voxels.iter().enumerate() {
+      let Some(color) = voxel else {
+        continue;
+      };
+
+      let index = match indices.get(color) {
+        Some(index) => *index,
+        None => {
+          palette.push(*color);
+          if palette.len() > 255 {
+            return Err(Error::TooManyColors(palette.len()));
+          }
+          indices.insert(*color, palette.len() as u8);
+          palette.len() as u8
+        }
+      };
+
+      let [x, y, z] = self.model_position(i);
+      xyzi.extend([x, y, z, index]);
+    }
+
+    let [width, height, depth] = self.size.map(|axis| axis as i32);
+    let mut size = Vec::new();
+    for axis in [width, depth, height] {
+      size.extend(axis.to_le_bytes());
+    }
+
+    let mut voxels = ((xyzi.len() / 4) as i32).to_le_bytes().to_vec();
+    voxels.extend(xyzi);
+
+    let mut rgba = palette.into_iter().flatten().collect::<Vec<_>>();
+    rgba.resize(256 * 4, 0);
+
+    let mut children = Vec::new();
+    write_chunk(&mut children, b"SIZE", &size, &[])?;
+    write_chunk(&mut children, b"XYZI", &voxels, &[])?;
+    write_chunk(&mut children, b"RGBA", &rgba, &[])?;
+
+    writer.write_all(b"VOX ")?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    write_chunk(&mut writer, b"MAIN", &[], &children)
+  }
+
+  pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+    self.write(std::io::BufWriter::new(std::fs::File::create(path)?))
+  }
+
+  /// Reads the first model of a vox file
+  pub fn read(mut reader: impl Read) -> Result<Self, Error> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+
+    if data.len() < 8 || &data[..4] != b"VOX " {
+      return Err(Error::InvalidHeader);
+    }
+
+    let mut size = None;
+    let mut xyzi = None;
+    let mut palette = None;
+
+    let mut rest = &data[8..];
+    while rest.len() >= 12 {
+      let id = &rest[..4];
+      let content_len = read_len(&rest[4..])?;
+      let children_len = read_len(&rest[8..])?;
+      let content_end = content_len.checked_add(12).ok_or(Error::InvalidHeader)?;
+      let content = rest.get(12..content_end).ok_or(Error::InvalidHeader)?;
+
+      match id {
+        // MAIN only has children, which are read as if they were siblings
+        b"MAIN" => {
+          rest = &rest[12..];
+          continue;
+        }
+        b"SIZE" if size.is_none() => {
+          let [x, y, z] = [
+            read_len(content)?,
+            read_len(content.get(4..).ok_or(Error::InvalidHeader)?)?,
+            read_len(content.get(8..).ok_or(Error::InvalidHeader)?)?,
+          ];
+          if [x, y, z].iter().any(|axis| *axis > MAX_SIZE) {
+            return Err(Error::TooLarge([x, z, y]));
+          }
+          size = Some([x, y, z]);
+        }
+        b"XYZI" if xyzi.is_none() => xyzi = Some(content.get(4..).ok_or(Error::InvalidHeader)?),
+        b"RGBA" => palette = Some(content.to_vec()),
+        _ => {}
+      }
+
+      rest = content_end
+        .checked_add(children_len)
+        .and_then(|end| rest.get(end..))
+        .ok_or(Error::InvalidHeader)?;
+    }
+
+    let [model_x, model_y, model_z] = size.ok_or(Error::MissingChunk("SIZE"))?;
+    let xyzi = xyzi.ok_or(Error::MissingChunk("XYZI"))?;
+    let palette = palette.unwrap_or_else(default_palette);
+
+    let mut voxels = Self {
+      size: [model_x, model_z, model_y],
+      voxels: vec![None; model_x * model_y * model_z],
+    };
+
+    for voxel in xyzi.chunks_exact(4) {
+      let [x, y, z, index] = [voxel[0], voxel[1], voxel[2], voxel[3]];
+
+      if x as usize >= model_x || y as usize >= model_y || z as usize >= model_z {
+        return Err(Error::OutOfBounds([x, y, z]));
+      }
+
+      // palette entries are offset by one, as index 0 is reserved for empty voxels
+      let offset = (index as usize).saturating_sub(1) * 4;
+      let color = [0, 1, 2, 3].map(|c| palette.get(offset + c).copied().unwrap_or_default());
+
+      let i = voxels.grid_index([x, y, z]);
+      voxels.voxels[i] = Some(color);
+    }
+
+    Ok(voxels)
+  }
+
+  pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+    Self::read(std::io::BufReader::new(std::fs::File::open(path)?))
+  }
+
+  fn model_position(&self, index: usize) -> [u8; 3] {
+    let [width, height, _] = self.size;
+    let [x, y, z] = [
+      index % width,
+      index / width % height,
+      index / (width * height),
+    ];
+    [x as u8, z as u8, (height - 1 - y) as u8]
+  }
+
+  fn grid_index(&self, [x, y, z]: [u8; 3]) -> usize {
+    let [width, height, _] = self.size;
+    let [x, y, z] = [x as usize, height - 1 - z as usize, y as usize];
+    x + y * width + z * width * height
+  }
+}
+
+fn write_chunk(
+  mut writer: impl Write,
+  id: &[u8; 4],
+  content: &[u8],
+  children: &[u8],
+) -> Result<(), Error> {
+  writer.write_all(id)?;
+  writer.write_all(&(content.len() as i32).to_le_bytes())?;
+  writer.write_all(&(children.len() as i32).to_le_bytes())?;
+  writer.write_all(content)?;
+  writer.write_all(children)?;
+  Ok(())
+}
+
+/// Checks that there is one value for every cell or voxel
+fn check_len<T>(values: &[T], expected: usize) -> Result<(), Error> {
+  if values.len() != expected {
+    return Err(Error::SizeMismatch {
+      expected,
+      found: values.len(),
+    });
+  }
+  Ok(())
+}
+
+/// Reads a little endian i32 that must not be negative
+fn read_len(bytes: &[u8]) -> Result<usize, Error> {
+  let bytes = bytes.get(..4).ok_or(Error::InvalidHeader)?;
+  let value = i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+  usize::try_from(value).map_err(|_| Error::InvalidHeader)
+}
+
+/// The palette MagicaVoxel uses when a model has no RGBA chunk, laid out like the chunk so entry 0 is color index 1.
+/// It holds a 6x6x6 color cube without black followed by red, green, blue and gray ramps
+fn default_palette() -> Vec<u8> {
+  const CUBE: [u8; 6] = [0xff, 0xcc, 0x99, 0x66, 0x33, 0x00];
+  const RAMP: [u8; 10] = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+
+  let cube = CUBE
+    .into_iter()
+    .flat_map(|r| CUBE.into_iter().flat_map(move |g| CUBE.map(|b| [r, g, b])))
+    .filter(|color| *color != [0, 0, 0]);
+  let ramps = [[1, 0, 0], [0, 1, 0], [0, 0, 1], [1, 1, 1]]
+    .into_iter()
+    .flat_map(|channels: [u8; 3]| RAMP.map(|v| channels.map(|c| c * v)));
+
+  let mut palette = cube
+    .chain(ramps)
+    .flat_map(|[r, g, b]| [r, g, b, 0xff])
+    .collect::<Vec<_>>();
+  palette.resize(256 * 4, 0);
+  palette
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{Color, Error, Voxels};
+  use crate::{
+    RuleBuilder, Rules, StateBuilder,
+    prebuilt::{Dim3d, constraints::UnaryConstraint, processing::RandomObserver},
+  };
+
+  const RED: Color = [255, 0, 0, 255];
+  const BLUE: Color = [0, 0, 255, 255];
+
+  #[test]
+  fn models_round_trip() {
+    let cells = [
+      Some(0),
+      None,
+      Some(1),
+      Some(0),
+      None,
+      None,
+      Some(1),
+      Some(1),
+    ];
+    let colors = |variant: &i32| Some(if *variant == 0 { RED } else { BLUE });
+
+    let voxels = Voxels::from_cells(&cells, [2, 2, 2].into(), colors).unwrap();
+
+    let mut bytes = Vec::new();
+    voxels.write(&mut bytes).unwrap();
+    assert_eq!(&bytes[..4], b"VOX ");
+
+    let read = Voxels::read(bytes.as_slice()).unwrap();
+    assert_eq!(read, voxels);
+    assert_eq!(read.sample().source[2], Some(BLUE));
+  }
+
+  #[test]
+  fn models_without_a_palette_use_the_default() {
+    let voxels = Voxels::from_cells(&[Some(0), Some(1)], [2, 1, 1].into(), |variant: &i32| {
+      Some(if *variant == 0 { RED } else { BLUE })
+    })
+    .unwrap();
+    let mut bytes = Vec::new();
+    voxels.write(&mut bytes).unwrap();
+
+    // drops the RGBA chunk, the last child of MAIN, and shrinks MAIN to match
+    let rgba_len = 12 + 256 * 4;
+    bytes.truncate(bytes.len() - rgba_len);
+    let children_len = i32::from_le_bytes(bytes[16..20].try_into().unwrap()) - rgba_len as i32;
+    bytes[16..20].copy_from_slice(&children_len.to_le_bytes());
+
+    let read = Voxels::read(bytes.as_slice()).unwrap();
+    assert_eq!(
+      read.voxels,
+      [Some([255, 255, 255, 255]), Some([255, 255, 204, 255])]
+    );
+  }
+
+  #[test]
+  fn malformed_models_are_rejected() {
+    let voxels = Voxels::from_cells(&[Some(0)], [1, 1, 1].into(), |_: &i32| Some(RED)).unwrap();
+    let mut bytes = Vec::new();
+    voxels.write(&mut bytes).unwrap();
+
+    // a SIZE chunk claiming to hold fewer bytes than its three axes
+    let mut short_size = bytes.clone();
+    short_size[24..28].copy_from_slice(&4i32.to_le_bytes());
+    assert!(matches!(
+      Voxels::read(short_size.as_slice()),
+      Err(Error::InvalidHeader)
+    ));
+
+    // a chunk length that is negative
+    let mut negative = bytes.clone();
+    negative[24..28].copy_from_slice(&(-1i32).to_le_bytes());
+    assert!(matches!(
+      Voxels::read(negative.as_slice()),
+      Err(Error::InvalidHeader)
+    ));
+
+    // truncated files fail rather than panic, unless only the optional palette was cut off before its header
+    let xyzi_end = bytes.len() - (12 + 256 * 4);
+    for len in 0..bytes.len() {
+      let palette_cut = (xyzi_end..xyzi_end + 12).contains(&len);
+      assert_eq!(Voxels::read(&bytes[..len]).is_ok(), palette_cut);
+    }
+  }
+
+  #[test]
+  fn blocks_expand_cells() {
+    let model = [Some(RED), None, None, None, None, None, None, Some(BLUE)];
+    let voxels = Voxels::from_blocks(&[Some('a'), None], [2, 1, 1].into(), [2, 2, 2], |_| {
+      Some(&model[..])
+    })
+    .unwrap();
+
+    assert_eq!(voxels.size, [4, 2, 2]);
+    assert_eq!(voxels.voxels[0], Some(RED));
+    assert_eq!(voxels.voxels[1 + 4 + 8], Some(BLUE));
+    assert_eq!(voxels.voxels[2], None);
+
+    assert!(matches!(
+      Voxels::from_blocks(&[Some('a')], [2, 1, 1].into(), [2, 2, 2], |_| Some(
+        &model[..]
+      )),
+      Err(Error::SizeMismatch {
+        expected: 2,
+        found: 1
+      })
+    ));
+    assert!(matches!(
+      Voxels::from_blocks(&[Some('a')], [1, 1, 1].into(), [2, 2, 2], |_| Some(
+        &model[1..]
+      )),
+      Err(Error::SizeMismatch {
+        expected: 8,
+        found: 7
+      })
+    ));
+  }
+
+  #[test]
+  fn states_are_exported() {
+    let rules: Rules<i32, Dim3d, i32> = RuleBuilder::default()
+      .with_rule(0, |_| 0)
+      .with_rule(1, |_| 1)
+      .into();
+
+    let mut state = StateBuilder::new(
+      [3, 4, 5],
+      RandomObserver::new(Some(7)),
+      UnaryConstraint,
+      rules,
+    )
+    .build()
+    .unwrap();
+    crate::collapse(&mut state).unwrap();
+
+    let voxels = Voxels::from_state(&state, |variant| Some([*variant as u8, 0, 0, 255])).unwrap();
+    let mut bytes = Vec::new();
+    voxels.write(&mut bytes).unwrap();
+
+    assert_eq!(Voxels::read(bytes.as_slice()).unwrap(), voxels);
+  }
+}
@@ -1,12 +1,16 @@
 pub(crate) mod auto;
+#[cfg(feature = "bevy")]
+pub mod bevy;
 pub(crate) mod cells;
 pub(crate) mod err;
 pub mod ext;
+pub mod io;
 #[cfg(feature = "profiling")]
 pub mod perf;
 pub mod prebuilt;
 pub(crate) mod rules;
 pub(crate) mod state;
+pub(crate) mod stats;
 pub(crate) mod util;
 
 use derive_more::derive::{Deref, DerefMut};
@@ -28,26 +32,27 @@ use strum::{EnumCount, IntoEnumIterator, VariantArray};
 pub mod prelude {
   pub use super::{
     Observation,
-    auto::{FindResult, NoSocket, RuleFinder, SocketProvider},
+    auto::{FindResult, NoSocket, RuleFinder, SocketFailure, SocketProvider},
     collapse,
     err::Error,
     prebuilt,
     rules::{AbstractRule, AbstractRules, Legend, Rule, RuleBuilder, Rules},
     state::{State, StateBuilder},
-    util::{IPos, Size, UPos},
+    stats::Stats,
+    util::{IPos, Metric, Size, UPos},
   };
 }
 
 pub use prelude::*;
 
-/// Collapses the state until an error occurs or is finished
+/// Collapses the state until an error occurs or is finished, returning the stats of the state
 #[profiling::function]
 pub fn collapse<A, C, V, D, S, const DIM: usize>(
   state: &mut State<A, C, V, D, S, DIM>,
-) -> Result<(), err::Error<DIM>>
+) -> Result<Stats, err::Error<DIM>>
 where
   A: Observer<V>,
-  C: Constraint<S>,
+  C: DirectionalConstraint<V, D, S>,
   V: Variant,
   D: Dimension,
   S: Socket,
@@ -57,7 +62,8 @@ where
       break;
     }
   }
-  Ok(())
+
+  Ok(*state.stats())
 }
 
 pub type CellIndex = usize;
@@ -80,8 +86,12 @@ pub struct SocketId(usize);
 #[cfg_attr(feature = "bevy", derive(bevy_reflect::Reflect))]
 pub struct DimensionId(usize);
 impl DimensionId {
-  #[allow(unused)]
-  fn opposite(self) -> Self {
+  /// The id of the direction, based on its position within the dimension
+  pub fn of<D: Dimension>(dir: &D) -> Self {
+    Self(D::iter().position(|d| d == *dir).unwrap())
+  }
+
+  pub fn opposite(self) -> Self {
     if *self & 1 == 0 {
       Self(*self + 1)
     } else {
@@ -120,6 +130,15 @@ pub trait Dimension:
   fn opposite(&self) -> Self;
 }
 
+/// A dimension with a plane that tiles can be rotated and reflected within
+pub trait Planar: Dimension {
+  /// The direction after a clockwise quarter turn
+  fn rotated(&self) -> Self;
+
+  /// The direction after mirroring left and right
+  fn reflected(&self) -> Self;
+}
+
 /// The successful result of a single collapse
 #[derive(PartialEq, Eq)]
 pub enum Observation {
@@ -154,7 +173,14 @@ pub trait Modifier<V: Variant> {
   type Chained<C: Modifier<V>>: Modifier<V>;
 
   /// Perform any mutations to the Cells upon a variant being selected
-  fn modify<D: Dimension, const DIM: usize>(&mut self, variant: &V, cells: &mut Cells<V, D, DIM>);
+  ///
+  /// Possibilities removed through [`Cells::retain`] are propagated once all modifiers have run
+  fn modify<D: Dimension, const DIM: usize>(
+    &mut self,
+    variant: &V,
+    index: CellIndex,
+    cells: &mut Cells<V, D, DIM>,
+  ) -> Result<(), err::Error<DIM>>;
 
   fn chain<A>(self, other: A) -> Self::Chained<A>
   where
@@ -166,6 +192,46 @@ pub trait Constraint<S: Socket>: Debug {
   fn check(&self, socket: &S, all_connecting_sockets: &HashSet<S>) -> bool;
 }
 
+/// Everything known about a variant and a neighboring cell when testing if they can connect
+#[derive(new, Debug)]
+pub struct Connection<'c, V, D, S> {
+  /// The variant being tested
+  pub variant: &'c V,
+  /// The socket of the variant facing the neighbor
+  pub socket: &'c S,
+  /// The direction from the cell to the neighbor
+  pub direction: D,
+  /// All remaining possibilities of the neighbor
+  pub neighbor_variants: &'c BTreeSet<V>,
+  /// The sockets of all remaining possibilities of the neighbor, facing the cell
+  pub neighbor_sockets: &'c HashSet<S>,
+}
+
+/// Trait that describes a type that is capable of checking if a variant is compatible with a neighboring cell,
+/// with access to the direction and the variants on both sides.
+///
+/// Implemented for all [`Constraint`] types, which only consider the sockets
+pub trait DirectionalConstraint<V: Variant, D: Dimension, S: Socket>: Debug {
+  fn allows(&self, connection: &Connection<'_, V, D, S>) -> bool;
+}
+
+impl<V, D, S, C> DirectionalConstraint<V, D, S> for C
+where
+  V: Variant,
+  D: Dimension,
+  S: Socket,
+  C: Constraint<S>,
+{
+  fn allows(&self, connection: &Connection<'_, V, D, S>) -> bool {
+    self.check(connection.socket, connection.neighbor_sockets)
+  }
+}
+
+/// Trait that describes a set of positions within a grid
+pub trait Region<const DIM: usize> {
+  fn contains(&self, pos: &UPos<DIM>) -> bool;
+}
+
 /// Trait that describes a valid weight
 pub trait Weight:
   SampleUniform
@@ -211,10 +277,14 @@ pub trait Shape: Debug {
 
 #[cfg(test)]
 mod tests {
-  use crate::{prelude::*, rules::RuleBuilder};
+  use crate::{Region, prelude::*, rules::RuleBuilder};
   use maplit::hashmap;
   use prebuilt::{
-    Dim2d, constraints::UnaryConstraint, processing::WeightedObserver, shapes::WeightedShape,
+    Dim2d,
+    constraints::UnaryConstraint,
+    processing::{RandomObserver, WeightedObserver},
+    regions::{Bounds, Predicate},
+    shapes::WeightedShape,
   };
 
   const SEED: u64 = 123;
@@ -300,4 +370,106 @@ mod tests {
 
     assert_eq!(a_data, b_data);
   }
+
+  #[test]
+  fn restrictions_are_applied() {
+    let rules: Rules<Tiles, Dim2d, Sockets> = RuleBuilder::default()
+      .with_rule(Tiles::TileA, Rule::splat(Sockets::Any))
+      .with_rule(Tiles::TileB, Rule::splat(Sockets::Any))
+      .with_rule(Tiles::TileC, Rule::splat(Sockets::Any))
+      .into();
+
+    let mut builder = StateBuilder::new(
+      [6, 6],
+      RandomObserver::new(Some(SEED)),
+      UnaryConstraint,
+      rules,
+    );
+
+    builder
+      .restrict(Bounds::new([0, 0], [6, 2]), [Tiles::TileA])
+      .restrict(
+        Predicate::new(|pos: &UPos<2>| pos[1] >= 2 && pos[0] % 2 == 1),
+        [Tiles::TileB, Tiles::TileC],
+      );
+
+    let mut state = builder.build().unwrap();
+
+    crate::collapse(&mut state).unwrap();
+
+    let size = *state.size();
+    let data: Vec<_> = state.into();
+
+    for (i, tile) in data.into_iter().enumerate() {
+      let pos = UPos::from_index(i, size);
+      if pos[1] < 2 {
+        assert_eq!(tile, Tiles::TileA);
+      } else if pos[0] % 2 == 1 {
+        assert_ne!(tile, Tiles::TileA);
+      }
+    }
+  }
+
+  #[test]
+  fn conflicting_restrictions_fail_to_build() {
+    let rules: Rules<Tiles, Dim2d, Sockets> = RuleBuilder::default()
+      .with_rule(Tiles::TileA, Rule::splat(Sockets::Any))
+      .with_rule(Tiles::TileB, Rule::splat(Sockets::Any))
+      .into();
+
+    let mut builder = StateBuilder::new(
+      [4, 4],
+      RandomObserver::new(Some(SEED)),
+      UnaryConstraint,
+      rules,
+    );
+
+    builder
+      .restrict(UPos::new([1, 1]), [Tiles::TileA])
+      .restrict(UPos::new([1, 1]), [Tiles::TileB]);
+
+    assert!(matches!(builder.build(), Err(Error::Exhausted { .. })));
+  }
+
+  #[test]
+  fn inpainting_keeps_surroundings() {
+    let rules: Rules<char, Dim2d, u8> = RuleBuilder::default()
+      .with_rule('a', |_| 0)
+      .with_rule('b', |_| 0)
+      .with_rule('c', |_| 1)
+      .into();
+
+    let mut state = StateBuilder::new(
+      [8, 8],
+      RandomObserver::new(Some(SEED)),
+      UnaryConstraint,
+      rules,
+    )
+    .build()
+    .unwrap();
+
+    crate::collapse(&mut state).unwrap();
+
+    let size = *state.size();
+    let region = Bounds::new([2, 2], [6, 6]);
+    let before = state.data_raw();
+
+    let state = state
+      .inpaint(region, RandomObserver::new(Some(SEED + 1)))
+      .unwrap();
+
+    let after = state.data_raw();
+
+    for (i, tile) in after.iter().enumerate() {
+      assert!(tile.is_some());
+
+      if !region.contains(&UPos::from_index(i, size)) {
+        assert_eq!(before[i], *tile);
+      }
+
+      for (neighbor, _) in &state.cells().at(i).neighbors {
+        assert_eq!(*tile == Some('c'), after[*neighbor] == Some('c'));
+      }
+    }
+  }
 }
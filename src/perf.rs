@@ -1,45 +1,367 @@
-use std::any::Any;
-use tracing;
-use tracing_subscriber;
-use tracing_subscriber::Registry;
+//! Profiling and performance metrics.
+//!
+//! Besides the spans of profiled functions, collapsing emits structured `tracing` events with the `wfc` target and a
+//! `kind` field of `collapse`, `contradiction` or `retry`. An [`Exporter`] writes these events as JSON lines to a
+//! path or writer, one line per event, so runs can be compared across commits
 
-#[must_use]
-pub fn enable_profiling() -> Vec<Box<dyn Any>> {
-  let subscriber = Registry::default();
-  let mut guards: Vec<Box<dyn Any>> = Vec::new();
+use crate::{Stats, err::Error as WfcError};
+use std::{
+  any::Any,
+  fmt::{Debug, Write as _},
+  fs::File,
+  io::{BufWriter, Write},
+  path::PathBuf,
+  sync::{Arc, Mutex},
+  time::Instant,
+};
+use tracing::{Event, Subscriber, field::Field};
+use tracing_subscriber::{Layer, Registry, layer::Context, layer::SubscriberExt};
 
-  #[cfg(feature = "tracing-tracy")]
-  let subscriber = {
-    use tracing_subscriber::layer::SubscriberExt;
-    use tracing_tracy::client::Client;
-    use tracing_tracy::TracyLayer;
+/// The target of every event emitted by collapsing
+pub const TARGET: &str = "wfc";
 
-    let (tracy_layer, tracy_client) = (TracyLayer::default(), Client::start());
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+  #[error(transparent)]
+  Io(#[from] std::io::Error),
+  #[error(transparent)]
+  Subscriber(#[from] tracing::subscriber::SetGlobalDefaultError),
+}
 
-    guards.push(Box::new(tracy_client));
-    subscriber.with(tracy_layer)
-  };
+/// Where the exporter writes to
+pub enum Output {
+  Path(PathBuf),
+  Writer(Box<dyn Write + Send>),
+}
+
+impl Output {
+  fn open(self) -> Result<Box<dyn Write + Send>, Error> {
+    Ok(match self {
+      Self::Path(path) => {
+        if let Some(parent) = path.parent() {
+          std::fs::create_dir_all(parent)?;
+        }
+        Box::new(BufWriter::new(File::create(path)?))
+      }
+      Self::Writer(writer) => writer,
+    })
+  }
+}
+
+impl Debug for Output {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Path(path) => f.debug_tuple("Path").field(path).finish(),
+      Self::Writer(_) => f.debug_tuple("Writer").finish_non_exhaustive(),
+    }
+  }
+}
+
+/// Configures which profiling layers are installed and where they write to
+#[derive(Debug, Default)]
+pub struct Exporter {
+  metrics: Option<Output>,
+  #[cfg(feature = "tracing-chrome")]
+  trace: Option<Output>,
+}
+
+impl Exporter {
+  /// Writes every `wfc` event as a line of JSON
+  pub fn with_metrics(mut self, output: Output) -> Self {
+    self.metrics = Some(output);
+    self
+  }
+
+  pub fn with_metrics_path(self, path: impl Into<PathBuf>) -> Self {
+    self.with_metrics(Output::Path(path.into()))
+  }
+
+  pub fn with_metrics_writer(self, writer: impl Write + Send + 'static) -> Self {
+    self.with_metrics(Output::Writer(Box::new(writer)))
+  }
+
+  /// Writes spans and events in the Chrome trace format
+  #[cfg(feature = "tracing-chrome")]
+  pub fn with_trace(mut self, output: Output) -> Self {
+    self.trace = Some(output);
+    self
+  }
+
+  #[cfg(feature = "tracing-chrome")]
+  pub fn with_trace_path(self, path: impl Into<PathBuf>) -> Self {
+    self.with_trace(Output::Path(path.into()))
+  }
+
+  #[cfg(feature = "tracing-chrome")]
+  pub fn with_trace_writer(self, writer: impl Write + Send + 'static) -> Self {
+    self.with_trace(Output::Writer(Box::new(writer)))
+  }
+
+  /// The subscriber with every configured layer, along with guards that must be kept alive while profiling
+  pub fn build(self) -> Result<(impl Subscriber + Send + Sync, Vec<Box<dyn Any>>), Error> {
+    let subscriber = Registry::default();
+    #[allow(unused_mut)]
+    let mut guards: Vec<Box<dyn Any>> = Vec::new();
+
+    #[cfg(feature = "tracing-tracy")]
+    let subscriber = {
+      use tracing_tracy::TracyLayer;
+      use tracing_tracy::client::Client;
+
+      let (tracy_layer, tracy_client) = (TracyLayer::default(), Client::start());
+
+      guards.push(Box::new(tracy_client));
+      subscriber.with(tracy_layer)
+    };
+
+    #[cfg(feature = "tracing-chrome")]
+    let subscriber = {
+      use tracing_chrome::ChromeLayerBuilder;
+
+      let chrome_layer = match self.trace {
+        Some(output) => {
+          let (chrome_layer, chrome_guard) =
+            ChromeLayerBuilder::new().writer(output.open()?).build();
+          guards.push(Box::new(chrome_guard));
+          Some(chrome_layer)
+        }
+        None => None,
+      };
+      subscriber.with(chrome_layer)
+    };
+
+    let metrics = match self.metrics {
+      Some(output) => Some(MetricsLayer::new(output.open()?)),
+      None => None,
+    };
+
+    Ok((subscriber.with(metrics), guards))
+  }
+
+  /// Installs the subscriber globally, returning guards that must be kept alive while profiling
+  #[must_use = "profiling stops once the guards are dropped"]
+  pub fn install(self) -> Result<Vec<Box<dyn Any>>, Error> {
+    let (subscriber, guards) = self.build()?;
+    tracing::subscriber::set_global_default(subscriber)?;
+    Ok(guards)
+  }
+}
+
+/// Installs Tracy and a Chrome trace in `target/`, depending on which are enabled
+#[must_use]
+pub fn enable_profiling() -> Vec<Box<dyn Any>> {
+  #[allow(unused_mut)]
+  let mut exporter = Exporter::default();
 
   #[cfg(feature = "tracing-chrome")]
-  let subscriber = {
+  {
     use chrono::prelude::*;
-    use std::fs::File;
-    use tracing_chrome::ChromeLayerBuilder;
-    use tracing_subscriber::layer::SubscriberExt;
 
     let output_file = format!("target/trace-{variant}.json", variant = Local::now());
-
     println!("Saving results to {output_file}");
+    exporter = exporter.with_trace_path(output_file);
+  }
 
-    let (chrome_layer, chrome_guard) = ChromeLayerBuilder::new()
-      .writer(File::create(output_file).expect("Failed to create trace file."))
-      .build();
+  exporter.install().expect("failed to setup profiling")
+}
 
-    guards.push(Box::new(chrome_guard));
-    subscriber.with(chrome_layer)
+/// Emitted once a state has fully collapsed
+pub(crate) fn collapse_event(stats: &Stats) {
+  tracing::info!(
+    target: TARGET,
+    kind = "collapse",
+    observations = stats.observations,
+    propagation_steps = stats.propagation_steps,
+    removed_possibilities = stats.removed_possibilities,
+    cache_hits = stats.cache_hits,
+    cache_misses = stats.cache_misses,
+    max_stack_depth = stats.max_stack_depth,
+    observe_us = stats.observe_time.as_micros() as u64,
+    propagate_us = stats.propagate_time.as_micros() as u64,
+    contradictions = stats.contradictions,
+  );
+}
+
+/// Emitted whenever a collapse step fails
+pub(crate) fn contradiction_event<const DIM: usize>(error: &WfcError<DIM>, stats: &Stats) {
+  tracing::warn!(
+    target: TARGET,
+    kind = "contradiction",
+    error = %error,
+    observations = stats.observations,
+  );
+}
+
+/// Records that a generation is being retried with a new seed, for callers that retry failed generations
+pub fn retry(seed: u64, attempt: u64) {
+  tracing::info!(target: TARGET, kind = "retry", seed, attempt);
+}
+
+/// Writes `wfc` events as JSON lines
+struct MetricsLayer {
+  writer: Arc<Mutex<Box<dyn Write + Send>>>,
+  start: Instant,
+}
+
+impl MetricsLayer {
+  fn new(writer: Box<dyn Write + Send>) -> Self {
+    Self {
+      writer: Arc::new(Mutex::new(writer)),
+      start: Instant::now(),
+    }
+  }
+}
+
+impl<S: Subscriber> Layer<S> for MetricsLayer {
+  fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+    if event.metadata().target() != TARGET {
+      return;
+    }
+
+    let mut line = JsonLine(format!(
+      "{{\"elapsed_us\":{}",
+      self.start.elapsed().as_micros()
+    ));
+    event.record(&mut line);
+    line.0.push_str("}\n");
+
+    let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+    // metrics are best effort, a failed write should not take down the generation
+    let _ = writer
+      .write_all(line.0.as_bytes())
+      .and_then(|_| writer.flush());
+  }
+}
+
+struct JsonLine(String);
+
+impl JsonLine {
+  fn key(&mut self, field: &Field) {
+    let _ = write!(self.0, ",\"{}\":", field.name());
+  }
+
+  fn string(&mut self, value: &str) {
+    self.0.push('"');
+    for c in value.chars() {
+      match c {
+        '"' => self.0.push_str("\\\""),
+        '\\' => self.0.push_str("\\\\"),
+        '\n' => self.0.push_str("\\n"),
+        c if c.is_control() => {
+          let _ = write!(self.0, "\\u{:04x}", c as u32);
+        }
+        c => self.0.push(c),
+      }
+    }
+    self.0.push('"');
+  }
+}
+
+impl tracing::field::Visit for JsonLine {
+  fn record_u64(&mut self, field: &Field, value: u64) {
+    self.key(field);
+    let _ = write!(self.0, "{value}");
+  }
+
+  fn record_i64(&mut self, field: &Field, value: i64) {
+    self.key(field);
+    let _ = write!(self.0, "{value}");
+  }
+
+  fn record_i128(&mut self, field: &Field, value: i128) {
+    self.key(field);
+    let _ = write!(self.0, "{value}");
+  }
+
+  fn record_u128(&mut self, field: &Field, value: u128) {
+    self.key(field);
+    let _ = write!(self.0, "{value}");
+  }
+
+  fn record_f64(&mut self, field: &Field, value: f64) {
+    self.key(field);
+    // JSON has no representation for infinities or NaN
+    if value.is_finite() {
+      let _ = write!(self.0, "{value}");
+    } else {
+      self.0.push_str("null");
+    }
+  }
+
+  fn record_bool(&mut self, field: &Field, value: bool) {
+    self.key(field);
+    let _ = write!(self.0, "{value}");
+  }
+
+  fn record_str(&mut self, field: &Field, value: &str) {
+    self.key(field);
+    self.string(value);
+  }
+
+  fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+    self.key(field);
+    self.string(&format!("{value:?}"));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{Exporter, TARGET};
+  use crate::{
+    RuleBuilder, Rules, StateBuilder,
+    prebuilt::{Dim2d, constraints::UnaryConstraint, processing::RandomObserver},
   };
+  use std::{
+    io::Write,
+    sync::{Arc, Mutex},
+  };
+
+  #[derive(Clone, Default)]
+  struct Shared(Arc<Mutex<Vec<u8>>>);
+
+  impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+      self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+      Ok(())
+    }
+  }
+
+  #[test]
+  fn events_are_exported_as_json_lines() {
+    let output = Shared::default();
+    let (subscriber, _guards) = Exporter::default()
+      .with_metrics_writer(output.clone())
+      .build()
+      .unwrap();
+
+    tracing::subscriber::with_default(subscriber, || {
+      let rules: Rules<u8, Dim2d, u8> = RuleBuilder::default()
+        .with_rule(0, |_| 0)
+        .with_rule(1, |_| 1)
+        .into();
+
+      let mut state =
+        StateBuilder::new([3, 3], RandomObserver::new(Some(4)), UnaryConstraint, rules)
+          .build()
+          .unwrap();
+      // stepping manually exports the same events as collapsing at once
+      while !state.collapse().unwrap().complete() {}
+
+      super::retry(5, 1);
+      tracing::info!(target: TARGET, kind = "floats", ratio = 0.5, infinite = f64::INFINITY);
+    });
 
-  tracing::subscriber::set_global_default(subscriber).expect("failed to setup tracy");
+    let text = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+    let lines = text.lines().collect::<Vec<_>>();
 
-  guards
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("{\"elapsed_us\":"));
+    assert!(lines[0].contains("\"kind\":\"collapse\",\"observations\":9,"));
+    assert!(lines[0].ends_with("\"contradictions\":0}"));
+    assert!(lines[1].ends_with("\"kind\":\"retry\",\"seed\":5,\"attempt\":1}"));
+    assert!(lines[2].ends_with("\"kind\":\"floats\",\"ratio\":0.5,\"infinite\":null}"));
+  }
 }
@@ -2,7 +2,10 @@ pub mod auto;
 pub mod constraints;
 pub mod dims;
 pub mod e2e;
+pub mod hierarchy;
+pub mod layers;
 pub mod processing;
+pub mod regions;
 pub mod shapes;
 
 pub use dims::*;
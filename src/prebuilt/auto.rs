@@ -1,19 +1,160 @@
 use self::auto::Error;
 use crate::{
-  auto,
+  Dimension, DimensionId, FindResult, Rule, RuleFinder, Rules, SocketFailure, SocketProvider,
+  Variant, auto,
+  prebuilt::{
+    constraints::{AdjacencyConstraint, AdjacencySocket, AdjacencyTable},
+    shapes::{NeighborShape, WeightedShape},
+  },
   rules::RuleBuilder,
-  util::{IPos, Size},
-  Dimension, FindResult, Rule, RuleFinder, Rules, SocketProvider,
+  util::{IPos, Size, UPos},
 };
-use std::{fmt::Debug, hash::Hash, marker::PhantomData};
+use nalgebra::SVector;
+use std::{
+  collections::{BTreeSet, HashMap},
+  fmt::Debug,
+  hash::Hash,
+  marker::PhantomData,
+};
+
+/// A grid of variants to learn rules from
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Sample<V, const DIM: usize> {
+  pub source: Vec<V>,
+  pub size: Size<DIM>,
+}
+
+impl<V, const DIM: usize> Sample<V, DIM> {
+  pub fn new(source: impl Into<Vec<V>>, size: impl Into<Size<DIM>>) -> Self {
+    Self {
+      source: source.into(),
+      size: size.into(),
+    }
+  }
+
+  /// Acquires the neighbor of the cell at the index in the direction.
+  /// Periodic axes wrap around to the other side of the sample, otherwise neighbors outside of the sample are the border variant
+  pub fn neighbor<'s, D: Dimension>(
+    &'s self,
+    index: usize,
+    dir: D,
+    periodic: &[bool; DIM],
+    border: Option<&'s V>,
+  ) -> Option<&'s V> {
+    let neighbor = IPos::from_index(index, self.size) + dir;
+    let wrapped = neighbor.wrap(self.size);
+    let neighbor = IPos::from(SVector::<isize, DIM>::from_fn(|i, _| {
+      if periodic[i] { wrapped[i] } else { neighbor[i] }
+    }));
+
+    if self.size.contains(&neighbor) {
+      self.source.get(neighbor.index(self.size))
+    } else {
+      border
+    }
+  }
+}
+
+pub type RulesWithFrequencies<V, D, S> = (Rules<V, D, Option<S>>, Frequencies<V, D>);
+
+pub type AdjacencyRules<V, D> = (
+  Rules<V, D, AdjacencySocket<V, D>>,
+  AdjacencyConstraint<V, D>,
+);
+
+/// How often variants, and pairs of neighboring variants, appear within samples
+#[derive(Debug)]
+pub struct Frequencies<V: Variant, D: Dimension> {
+  variants: HashMap<V, usize>,
+  neighbors: HashMap<(V, D, V), usize>,
+}
+
+impl<V: Variant, D: Dimension> Default for Frequencies<V, D> {
+  fn default() -> Self {
+    Self {
+      variants: Default::default(),
+      neighbors: Default::default(),
+    }
+  }
+}
+
+impl<V: Variant, D: Dimension> Clone for Frequencies<V, D> {
+  fn clone(&self) -> Self {
+    Self {
+      variants: self.variants.clone(),
+      neighbors: self.neighbors.clone(),
+    }
+  }
+}
+
+impl<V: Variant, D: Dimension> Frequencies<V, D> {
+  /// The number of times the variant appeared
+  pub fn count(&self, variant: &V) -> usize {
+    self.variants.get(variant).cloned().unwrap_or_default()
+  }
+
+  /// The number of times the neighbor appeared in the direction of the variant
+  pub fn neighbor_count(&self, variant: &V, dir: D, neighbor: &V) -> usize {
+    self
+      .neighbors
+      .get(&(variant.clone(), dir, neighbor.clone()))
+      .cloned()
+      .unwrap_or_default()
+  }
+
+  pub fn variants(&self) -> &HashMap<V, usize> {
+    &self.variants
+  }
+
+  pub fn neighbors(&self) -> &HashMap<(V, D, V), usize> {
+    &self.neighbors
+  }
+
+  /// A shape weighting every variant by how often it appeared
+  pub fn weights(&self) -> WeightedShape<V, usize> {
+    WeightedShape::new(self.variants.clone())
+  }
+
+  /// A shape weighting every variant by how often it appeared next to the collapsed neighbors of a cell
+  pub fn neighbor_shape(&self) -> NeighborShape<V, usize> {
+    NeighborShape::new(
+      self.variants.clone(),
+      self
+        .neighbors
+        .iter()
+        .map(|((variant, dir, neighbor), count)| {
+          (
+            (variant.clone(), DimensionId::of(dir), neighbor.clone()),
+            *count,
+          )
+        })
+        .collect::<HashMap<_, _>>(),
+    )
+    .with_smoothing(1)
+  }
+
+  fn record(&mut self, variant: &V) {
+    *self.variants.entry(variant.clone()).or_default() += 1;
+  }
 
+  fn record_neighbor(&mut self, variant: &V, dir: D, neighbor: &V) {
+    *self
+      .neighbors
+      .entry((variant.clone(), dir, neighbor.clone()))
+      .or_default() += 1;
+  }
+}
+
+/// Finds rules by scanning any number of samples, merging the sockets of every sample together
 pub struct GenericFinder<V, D, S, P, const DIM: usize>
 where
   P: SocketProvider<V, D, S>,
 {
-  source: Vec<V>,
-  size: Size<DIM>,
+  samples: Vec<Sample<V, DIM>>,
   provider: P,
+  periodic: [bool; DIM],
+  border: Option<V>,
   _pd: PhantomData<(S, D)>,
 }
 
@@ -23,14 +164,46 @@ where
   P: SocketProvider<V, D, S>,
 {
   pub fn new(provider: P, source: impl Into<Vec<V>>, size: impl Into<Size<DIM>>) -> Self {
+    Self::from_samples(provider, [Sample::new(source, size)])
+  }
+
+  pub fn from_samples(provider: P, samples: impl IntoIterator<Item = Sample<V, DIM>>) -> Self {
     Self {
-      source: source.into(),
-      size: size.into(),
+      samples: samples.into_iter().collect(),
       provider,
+      periodic: [false; DIM],
+      border: None,
       _pd: PhantomData,
     }
   }
 
+  /// Treats the samples as periodic along each axis that is set,
+  /// so the cells on one side of a sample neighbor the cells on the other side
+  pub fn with_periodic(mut self, periodic: [bool; DIM]) -> Self {
+    self.periodic = periodic;
+    self
+  }
+
+  /// Treats everything outside of the samples as the variant, along any axis that is not periodic
+  pub fn with_border(mut self, border: V) -> Self {
+    self.border = Some(border);
+    self
+  }
+
+  pub fn add_sample(&mut self, source: impl Into<Vec<V>>, size: impl Into<Size<DIM>>) -> &mut Self {
+    self.samples.push(Sample::new(source, size));
+    self
+  }
+
+  pub fn with_sample(mut self, source: impl Into<Vec<V>>, size: impl Into<Size<DIM>>) -> Self {
+    self.add_sample(source, size);
+    self
+  }
+
+  pub fn samples(&self) -> &[Sample<V, DIM>] {
+    &self.samples
+  }
+
   #[profiling::function]
   fn get_socket(
     &self,
@@ -38,17 +211,8 @@ where
     dir: D,
     variant: &V,
     neighbor_variant: &V,
-    failures: &mut Vec<(D, V, V)>,
-  ) -> FindResult<P::WorkingType>
-  where
-    V: Clone,
-  {
-    self
-      .provider
-      .find(current, dir, variant, neighbor_variant)
-      .inspect_err(|_| {
-        failures.push((dir, variant.clone(), neighbor_variant.clone()));
-      })
+  ) -> FindResult<P::WorkingType> {
+    self.provider.find(current, dir, variant, neighbor_variant)
   }
 }
 
@@ -59,32 +223,69 @@ where
   S: Debug + Eq + Hash + Ord + Clone,
   P: SocketProvider<V, D, S>,
 {
+  /// Scans every sample, reporting all sockets that could not be found along with the sample and position they occurred at
   #[profiling::function]
   fn find(&self) -> Result<Rules<V, D, Option<S>>, Error<V, D>> {
+    self.scan(None)
+  }
+}
+
+impl<V, D, S, P, const DIM: usize> GenericFinder<V, D, S, P, DIM>
+where
+  V: Variant,
+  D: Dimension,
+  S: Debug + Eq + Hash + Ord + Clone,
+  P: SocketProvider<V, D, S>,
+{
+  /// Finds the rules, along with the frequencies of every variant and neighbor pair within the samples
+  #[profiling::function]
+  pub fn find_with_frequencies(&self) -> Result<RulesWithFrequencies<V, D, S>, Error<V, D>> {
+    let mut frequencies = Frequencies::default();
+    let rules = self.scan(Some(&mut frequencies))?;
+    Ok((rules, frequencies))
+  }
+
+  fn scan(
+    &self,
+    mut frequencies: Option<&mut Frequencies<V, D>>,
+  ) -> Result<Rules<V, D, Option<S>>, Error<V, D>> {
     let mut failures = Vec::new();
     let mut rule_builder = RuleBuilder::<V, D, Option<P::WorkingType>>::default();
 
-    for (i, source) in self.source.iter().enumerate() {
-      let pos = IPos::from_index(i, self.size);
-      let entry = rule_builder.entry(source.clone());
-      let rule = entry.or_insert_with(Rule::default);
-
-      for dir in D::iter() {
-        let neighbor = pos + dir;
+    for (sample_index, sample) in self.samples.iter().enumerate() {
+      for (i, source) in sample.source.iter().enumerate() {
+        let entry = rule_builder.entry(source.clone());
+        let rule = entry.or_insert_with(Rule::default);
 
-        if !self.size.contains(&neighbor) {
-          continue;
+        if let Some(frequencies) = frequencies.as_mut() {
+          frequencies.record(source);
         }
 
-        let neighbor_index = neighbor.index(self.size);
-        let target = &self.source[neighbor_index];
+        for dir in D::iter() {
+          let Some(target) = sample.neighbor(i, dir, &self.periodic, self.border.as_ref()) else {
+            continue;
+          };
 
-        let current_socket = rule.remove(&dir).unwrap_or(None);
-        let new_socket = self
-          .get_socket(current_socket, dir, source, target, &mut failures)
-          .map_err(|_| Error::SocketGenerationFailure(source.clone(), dir, target.clone()))?;
+          if let Some(frequencies) = frequencies.as_mut() {
+            frequencies.record_neighbor(source, dir, target);
+          }
 
-        rule.insert(dir, new_socket);
+          let current_socket = rule.remove(&dir).unwrap_or(None);
+          let new_socket = self
+            .get_socket(current_socket.clone(), dir, source, target)
+            .unwrap_or_else(|_| {
+              failures.push(SocketFailure {
+                sample: sample_index,
+                position: UPos::from_index(i, sample.size).iter().cloned().collect(),
+                dir,
+                source: source.clone(),
+                target: target.clone(),
+              });
+              current_socket
+            });
+
+          rule.insert(dir, new_socket);
+        }
       }
     }
 
@@ -114,3 +315,244 @@ where
     }
   }
 }
+
+/// Learns which variants may neighbor each other directly from samples, without any sockets.
+/// Every pair of variants that appear next to each other within a sample is allowed
+pub struct AdjacencyFinder<V: Variant, D: Dimension, const DIM: usize> {
+  samples: Vec<Sample<V, DIM>>,
+  periodic: [bool; DIM],
+  border: Option<V>,
+  _pd: PhantomData<D>,
+}
+
+impl<V: Variant, D: Dimension, const DIM: usize> AdjacencyFinder<V, D, DIM> {
+  pub fn new(source: impl Into<Vec<V>>, size: impl Into<Size<DIM>>) -> Self {
+    Self::from_samples([Sample::new(source, size)])
+  }
+
+  pub fn from_samples(samples: impl IntoIterator<Item = Sample<V, DIM>>) -> Self {
+    Self {
+      samples: samples.into_iter().collect(),
+      periodic: [false; DIM],
+      border: None,
+      _pd: PhantomData,
+    }
+  }
+
+  /// Treats the samples as periodic along each axis that is set
+  pub fn with_periodic(mut self, periodic: [bool; DIM]) -> Self {
+    self.periodic = periodic;
+    self
+  }
+
+  /// Treats everything outside of the samples as the variant, along any axis that is not periodic.
+  /// The border variant only receives a rule if it also appears within a sample
+  pub fn with_border(mut self, border: V) -> Self {
+    self.border = Some(border);
+    self
+  }
+
+  pub fn add_sample(&mut self, source: impl Into<Vec<V>>, size: impl Into<Size<DIM>>) -> &mut Self {
+    self.samples.push(Sample::new(source, size));
+    self
+  }
+
+  pub fn with_sample(mut self, source: impl Into<Vec<V>>, size: impl Into<Size<DIM>>) -> Self {
+    self.add_sample(source, size);
+    self
+  }
+
+  pub fn samples(&self) -> &[Sample<V, DIM>] {
+    &self.samples
+  }
+
+  /// Every (variant, direction, neighbor) triple observed within the samples
+  #[profiling::function]
+  pub fn table(&self) -> AdjacencyTable<V, D> {
+    let mut table = AdjacencyTable::default();
+
+    for sample in &self.samples {
+      for (i, source) in sample.source.iter().enumerate() {
+        for dir in D::iter() {
+          if let Some(target) = sample.neighbor(i, dir, &self.periodic, self.border.as_ref()) {
+            table.add(source.clone(), dir, target.clone());
+          }
+        }
+      }
+    }
+
+    table
+  }
+
+  /// Finds the rules for every variant within the samples, along with the constraint that connects them
+  #[profiling::function]
+  pub fn find(&self) -> AdjacencyRules<V, D> {
+    let variants = self
+      .samples
+      .iter()
+      .flat_map(|sample| sample.source.iter())
+      .collect::<BTreeSet<_>>();
+
+    let rules = variants
+      .into_iter()
+      .map(|variant| (variant.clone(), Rule::from_fn(|dir| (variant.clone(), dir))))
+      .collect::<RuleBuilder<_, _, _>>();
+
+    (rules.into(), AdjacencyConstraint::new(self.table()))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{AdjacencyFinder, GenericFinder};
+  use crate::{
+    FindResult, NoSocket, RuleFinder, SocketProvider, StateBuilder,
+    auto::Error,
+    prebuilt::{Dim1d, Dim2d, processing::RandomObserver},
+  };
+  use std::collections::BTreeSet;
+
+  /// Sockets are the set of all neighbors seen in a direction, '!' has no socket
+  struct Neighbors;
+
+  impl SocketProvider<char, Dim1d, BTreeSet<char>> for Neighbors {
+    type WorkingType = BTreeSet<char>;
+
+    fn find(
+      &self,
+      current: Option<Self::WorkingType>,
+      _dir: Dim1d,
+      _source: &char,
+      target: &char,
+    ) -> FindResult<Self::WorkingType> {
+      if *target == '!' {
+        return Err(NoSocket);
+      }
+
+      let mut socket = current.unwrap_or_default();
+      socket.insert(*target);
+      Ok(Some(socket))
+    }
+
+    fn finalize(&self, _dir: Dim1d, socket: Self::WorkingType) -> BTreeSet<char> {
+      socket
+    }
+  }
+
+  #[test]
+  fn samples_are_merged() {
+    let finder = GenericFinder::new(Neighbors, ['a', 'b'], [2])
+      .with_sample(['a', 'c', 'c'], [3])
+      .with_sample(['d'], [1]);
+
+    let rules = finder.find().unwrap();
+
+    let rule = rules.rule_for(&'a').unwrap();
+    assert_eq!(
+      rule.socket_for(&Dim1d::Right),
+      Some(&Some(BTreeSet::from(['b', 'c'])))
+    );
+    assert_eq!(rule.socket_for(&Dim1d::Left), None);
+
+    let rule = rules.rule_for(&'c').unwrap();
+    assert_eq!(
+      rule.socket_for(&Dim1d::Left),
+      Some(&Some(BTreeSet::from(['a', 'c'])))
+    );
+
+    assert!(rules.rule_for(&'d').unwrap().is_empty());
+  }
+
+  #[test]
+  fn failures_report_their_location() {
+    let finder = GenericFinder::new(Neighbors, ['a', 'b'], [2]).with_sample(['a', 'b', '!'], [3]);
+
+    let Err(Error::RuleNotFound(failures)) = finder.find() else {
+      panic!("expected failures");
+    };
+
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].sample, 1);
+    assert_eq!(failures[0].position, vec![1]);
+    assert_eq!(failures[0].dir, Dim1d::Right);
+  }
+
+  #[test]
+  fn frequencies_are_counted() {
+    let finder = GenericFinder::new(Neighbors, ['a', 'b', 'b'], [3]).with_sample(['b'], [1]);
+
+    let (_rules, frequencies) = finder.find_with_frequencies().unwrap();
+
+    assert_eq!(frequencies.count(&'a'), 1);
+    assert_eq!(frequencies.count(&'b'), 3);
+    assert_eq!(frequencies.neighbor_count(&'a', Dim1d::Right, &'b'), 1);
+    assert_eq!(frequencies.neighbor_count(&'b', Dim1d::Left, &'a'), 1);
+    assert_eq!(frequencies.neighbor_count(&'b', Dim1d::Right, &'b'), 1);
+    assert_eq!(frequencies.neighbor_count(&'a', Dim1d::Left, &'b'), 0);
+  }
+
+  #[test]
+  fn periodic_samples_wrap() {
+    let finder = GenericFinder::new(Neighbors, ['a', 'b', 'c'], [3]).with_periodic([true]);
+
+    let rules = finder.find().unwrap();
+
+    assert_eq!(
+      rules.rule_for(&'a').unwrap().socket_for(&Dim1d::Left),
+      Some(&Some(BTreeSet::from(['c'])))
+    );
+    assert_eq!(
+      rules.rule_for(&'c').unwrap().socket_for(&Dim1d::Right),
+      Some(&Some(BTreeSet::from(['a'])))
+    );
+  }
+
+  #[test]
+  fn borders_surround_samples() {
+    let finder = GenericFinder::new(Neighbors, ['a', 'b'], [2]).with_border('#');
+
+    let rules = finder.find().unwrap();
+
+    assert_eq!(
+      rules.rule_for(&'a').unwrap().socket_for(&Dim1d::Left),
+      Some(&Some(BTreeSet::from(['#'])))
+    );
+    assert_eq!(
+      rules.rule_for(&'b').unwrap().socket_for(&Dim1d::Right),
+      Some(&Some(BTreeSet::from(['#'])))
+    );
+  }
+
+  #[test]
+  fn adjacency_is_learned_without_sockets() {
+    #[rustfmt::skip]
+    let sample = [
+      '~', '~', '.', '^',
+      '~', '.', '.', '^',
+      '~', '~', '.', '.',
+    ];
+
+    let finder = AdjacencyFinder::<char, Dim2d, 2>::new(sample, [4, 3]);
+    let table = finder.table();
+
+    assert!(table.allows(&'~', Dim2d::Right, &'.'));
+    assert!(table.allows(&'.', Dim2d::Left, &'~'));
+    assert!(!table.allows(&'~', Dim2d::Right, &'^'));
+
+    let (rules, constraint) = finder.find();
+
+    let mut state = StateBuilder::new([10, 10], RandomObserver::new(Some(123)), constraint, rules)
+      .build()
+      .unwrap();
+
+    crate::collapse(&mut state).unwrap();
+
+    let data = state.data();
+
+    for (i, cell) in state.cells().list.iter().enumerate() {
+      for (neighbor, dir) in &cell.neighbors {
+        assert!(table.allows(&data[i], *dir, &data[*neighbor]));
+      }
+    }
+  }
+}
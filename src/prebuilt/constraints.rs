@@ -1,6 +1,6 @@
-use crate::{Constraint, Socket};
+use crate::{Constraint, Dimension, Rule, RuleBuilder, Rules, Socket, Variant};
 use std::{
-  collections::{BTreeSet, HashSet},
+  collections::{BTreeMap, BTreeSet, HashMap, HashSet},
   fmt::Debug,
 };
 
@@ -28,3 +28,487 @@ impl<S: Socket> Constraint<BTreeSet<S>> for SetConstraint {
       .any(|connecting_sockets| !connecting_sockets.is_disjoint(socket))
   }
 }
+
+/// Connects sockets through an explicit compatibility table, such as a plug connecting to a socket.
+///
+/// Pairs added with [`MatrixConstraint::with_pair`] only connect one way, so the reverse pair should
+/// normally be declared as well, or the pair declared with [`MatrixConstraint::with_symmetric_pair`].
+/// Wildcard sockets connect to anything
+#[derive(Debug)]
+pub struct MatrixConstraint<S: Socket> {
+  table: HashMap<S, HashSet<S>>,
+  wildcards: HashSet<S>,
+}
+
+impl<S: Socket> Default for MatrixConstraint<S> {
+  fn default() -> Self {
+    Self {
+      table: Default::default(),
+      wildcards: Default::default(),
+    }
+  }
+}
+
+impl<S: Socket> Clone for MatrixConstraint<S> {
+  fn clone(&self) -> Self {
+    Self {
+      table: self.table.clone(),
+      wildcards: self.wildcards.clone(),
+    }
+  }
+}
+
+impl<S: Socket> MatrixConstraint<S> {
+  /// Allows the socket to connect to the other socket
+  pub fn add_pair(&mut self, socket: S, other: S) -> &mut Self {
+    self.table.entry(socket).or_default().insert(other);
+    self
+  }
+
+  pub fn with_pair(mut self, socket: S, other: S) -> Self {
+    self.add_pair(socket, other);
+    self
+  }
+
+  /// Allows both sockets to connect to each other
+  pub fn add_symmetric_pair(&mut self, socket: S, other: S) -> &mut Self {
+    self.add_pair(socket.clone(), other.clone());
+    self.add_pair(other, socket)
+  }
+
+  pub fn with_symmetric_pair(mut self, socket: S, other: S) -> Self {
+    self.add_symmetric_pair(socket, other);
+    self
+  }
+
+  /// Allows the socket to connect to any other socket, and any other socket to connect to it
+  pub fn add_wildcard(&mut self, socket: S) -> &mut Self {
+    self.wildcards.insert(socket);
+    self
+  }
+
+  pub fn with_wildcard(mut self, socket: S) -> Self {
+    self.add_wildcard(socket);
+    self
+  }
+
+  pub fn connects(&self, socket: &S, other: &S) -> bool {
+    self.wildcards.contains(socket)
+      || self.wildcards.contains(other)
+      || self
+        .table
+        .get(socket)
+        .is_some_and(|compatible| compatible.contains(other))
+  }
+
+  /// Iterates over every declared pair, including both directions of symmetric pairs
+  pub fn pairs(&self) -> impl Iterator<Item = (&S, &S)> {
+    self
+      .table
+      .iter()
+      .flat_map(|(socket, compatible)| compatible.iter().map(move |other| (socket, other)))
+  }
+
+  pub fn wildcards(&self) -> impl Iterator<Item = &S> {
+    self.wildcards.iter()
+  }
+}
+
+impl<S: Socket> Constraint<S> for MatrixConstraint<S> {
+  #[profiling::function]
+  fn check(&self, socket: &S, all_connecting_sockets: &HashSet<S>) -> bool {
+    if self.wildcards.contains(socket) {
+      return !all_connecting_sockets.is_empty();
+    }
+
+    if !self.wildcards.is_empty()
+      && all_connecting_sockets
+        .iter()
+        .any(|other| self.wildcards.contains(other))
+    {
+      return true;
+    }
+
+    let Some(compatible) = self.table.get(socket) else {
+      return false;
+    };
+
+    // iterate whichever set is smaller
+    if compatible.len() < all_connecting_sockets.len() {
+      compatible
+        .iter()
+        .any(|other| all_connecting_sockets.contains(other))
+    } else {
+      all_connecting_sockets
+        .iter()
+        .any(|other| compatible.contains(other))
+    }
+  }
+}
+
+/// Every variant that may appear next to each variant, in each direction
+#[derive(Debug)]
+pub struct AdjacencyTable<V: Variant, D: Dimension> {
+  table: HashMap<(V, D), BTreeSet<V>>,
+}
+
+impl<V: Variant, D: Dimension> Default for AdjacencyTable<V, D> {
+  fn default() -> Self {
+    Self {
+      table: Default::default(),
+    }
+  }
+}
+
+impl<V: Variant, D: Dimension> Clone for AdjacencyTable<V, D> {
+  fn clone(&self) -> Self {
+    Self {
+      table: self.table.clone(),
+    }
+  }
+}
+
+impl<V: Variant, D: Dimension> AdjacencyTable<V, D> {
+  /// Allows the neighbor in the direction of the variant, and the variant in the opposite direction of the neighbor
+  pub fn add(&mut self, variant: V, dir: D, neighbor: V) -> &mut Self {
+    self
+      .table
+      .entry((neighbor.clone(), dir.opposite()))
+      .or_default()
+      .insert(variant.clone());
+    self
+      .table
+      .entry((variant, dir))
+      .or_default()
+      .insert(neighbor);
+    self
+  }
+
+  pub fn with(mut self, variant: V, dir: D, neighbor: V) -> Self {
+    self.add(variant, dir, neighbor);
+    self
+  }
+
+  pub fn allows(&self, variant: &V, dir: D, neighbor: &V) -> bool {
+    self
+      .neighbors(variant, dir)
+      .is_some_and(|neighbors| neighbors.contains(neighbor))
+  }
+
+  /// All variants that may appear in the direction of the variant
+  pub fn neighbors(&self, variant: &V, dir: D) -> Option<&BTreeSet<V>> {
+    self.table.get(&(variant.clone(), dir))
+  }
+
+  /// Every variant that has at least one allowed neighbor
+  pub fn variants(&self) -> BTreeSet<&V> {
+    self.table.keys().map(|(variant, _)| variant).collect()
+  }
+
+  /// Iterates over every allowed (variant, direction, neighbor) triple, including both directions of each pair
+  pub fn iter(&self) -> impl Iterator<Item = (&V, D, &V)> {
+    self.table.iter().flat_map(|((variant, dir), neighbors)| {
+      neighbors
+        .iter()
+        .map(move |neighbor| (variant, *dir, neighbor))
+    })
+  }
+}
+
+impl<V: Variant, D: Dimension> AdjacencyTable<V, D> {
+  /// Infers a compact set of sockets for use with [`SetConstraint`] that allows exactly the same neighbors as the table.
+  ///
+  /// Along each axis, variants with identical neighbor sets are grouped into classes. Each class on the side with fewer
+  /// classes receives a single socket, and the variants on the other side receive the set of every class they may neighbor
+  #[profiling::function]
+  pub fn infer_sockets(&self) -> Rules<V, D, BTreeSet<usize>> {
+    let variants = self.variants();
+    let mut builder = variants
+      .iter()
+      .map(|variant| {
+        (
+          (*variant).clone(),
+          Rule::from_fn(|_| BTreeSet::<usize>::new()),
+        )
+      })
+      .collect::<RuleBuilder<V, D, BTreeSet<usize>>>();
+
+    let mut next_socket = 0;
+
+    for dir in D::iter().filter(|dir| *dir < dir.opposite()) {
+      let forward = self.classes(&variants, dir);
+      let backward = self.classes(&variants, dir.opposite());
+
+      let (single, single_dir, multi_dir) = if forward.len() <= backward.len() {
+        (forward, dir, dir.opposite())
+      } else {
+        (backward, dir.opposite(), dir)
+      };
+
+      for (neighbors, members) in single {
+        let socket = next_socket;
+        next_socket += 1;
+
+        for variant in members {
+          if let Some(rule) = builder.table.get_mut(variant) {
+            rule.entry(single_dir).or_default().insert(socket);
+          }
+        }
+
+        for neighbor in neighbors {
+          if let Some(rule) = builder.table.get_mut(neighbor) {
+            rule.entry(multi_dir).or_default().insert(socket);
+          }
+        }
+      }
+    }
+
+    builder.into()
+  }
+
+  /// Groups variants by their neighbor sets in the direction
+  fn classes<'v>(
+    &'v self,
+    variants: &BTreeSet<&'v V>,
+    dir: D,
+  ) -> BTreeMap<&'v BTreeSet<V>, Vec<&'v V>> {
+    let mut classes = BTreeMap::<_, Vec<_>>::new();
+    for variant in variants {
+      if let Some(neighbors) = self.neighbors(variant, dir) {
+        classes.entry(neighbors).or_default().push(*variant);
+      }
+    }
+    classes
+  }
+}
+
+impl<V: Variant, D: Dimension> FromIterator<(V, D, V)> for AdjacencyTable<V, D> {
+  fn from_iter<T: IntoIterator<Item = (V, D, V)>>(iter: T) -> Self {
+    let mut table = Self::default();
+    for (variant, dir, neighbor) in iter {
+      table.add(variant, dir, neighbor);
+    }
+    table
+  }
+}
+
+/// The socket of a variant facing a direction, used with [`AdjacencyConstraint`]
+pub type AdjacencySocket<V, D> = (V, D);
+
+/// Connects variants directly through an [`AdjacencyTable`], ignoring socket design entirely.
+/// Every socket is the variant itself along with the direction it faces
+#[derive(Debug)]
+pub struct AdjacencyConstraint<V: Variant, D: Dimension> {
+  table: AdjacencyTable<V, D>,
+}
+
+impl<V: Variant, D: Dimension> Clone for AdjacencyConstraint<V, D> {
+  fn clone(&self) -> Self {
+    Self {
+      table: self.table.clone(),
+    }
+  }
+}
+
+impl<V: Variant, D: Dimension> AdjacencyConstraint<V, D> {
+  pub fn new(table: AdjacencyTable<V, D>) -> Self {
+    Self { table }
+  }
+
+  pub fn table(&self) -> &AdjacencyTable<V, D> {
+    &self.table
+  }
+
+  /// Rules for every variant within the table, where each socket is the variant and the direction it faces
+  pub fn rules(&self) -> Rules<V, D, AdjacencySocket<V, D>> {
+    self
+      .table
+      .variants()
+      .into_iter()
+      .map(|variant| (variant.clone(), Rule::from_fn(|dir| (variant.clone(), dir))))
+      .collect::<RuleBuilder<_, _, _>>()
+      .into()
+  }
+}
+
+impl<V: Variant, D: Dimension> Constraint<AdjacencySocket<V, D>> for AdjacencyConstraint<V, D> {
+  #[profiling::function]
+  fn check(
+    &self,
+    (variant, dir): &AdjacencySocket<V, D>,
+    all_connecting_sockets: &HashSet<AdjacencySocket<V, D>>,
+  ) -> bool {
+    let Some(neighbors) = self.table.neighbors(variant, *dir) else {
+      return false;
+    };
+
+    all_connecting_sockets
+      .iter()
+      .any(|(neighbor, _)| neighbors.contains(neighbor))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{AdjacencyTable, MatrixConstraint, SetConstraint};
+  use crate::{
+    Connection, Constraint, Dimension, DirectionalConstraint, Rule, RuleBuilder, Rules,
+    StateBuilder,
+    prebuilt::{Dim2d, processing::RandomObserver},
+  };
+  use maplit::{hashmap, hashset};
+  use std::collections::HashSet;
+  use strum::IntoEnumIterator;
+
+  const SEED: u64 = 123;
+
+  /// Sockets must mirror horizontally but match exactly vertically
+  #[derive(Debug)]
+  struct MirrorX;
+
+  impl DirectionalConstraint<char, Dim2d, String> for MirrorX {
+    fn allows(&self, connection: &Connection<'_, char, Dim2d, String>) -> bool {
+      match connection.direction {
+        Dim2d::Left | Dim2d::Right => {
+          let mirrored = connection.socket.chars().rev().collect::<String>();
+          connection.neighbor_sockets.contains(&mirrored)
+        }
+        Dim2d::Up | Dim2d::Down => connection.neighbor_sockets.contains(connection.socket),
+      }
+    }
+  }
+
+  #[test]
+  fn directional_constraints_are_applied() {
+    let sockets = |left: &str, right: &str| {
+      hashmap! {
+        Dim2d::Left => left.to_string(),
+        Dim2d::Right => right.to_string(),
+        Dim2d::Up => "x".to_string(),
+        Dim2d::Down => "x".to_string(),
+      }
+    };
+
+    let rules: Rules<char, Dim2d, String> = RuleBuilder::default()
+      .with_rule('a', sockets("01", "10"))
+      .with_rule('b', sockets("10", "01"))
+      .with_rule('c', sockets("01", "01"))
+      .into();
+
+    let mut state = StateBuilder::new(
+      [8, 8],
+      RandomObserver::new(Some(SEED)),
+      MirrorX,
+      rules.clone(),
+    )
+    .build()
+    .unwrap();
+
+    crate::collapse(&mut state).unwrap();
+
+    let data = state.data_raw();
+
+    for (i, cell) in state.cells().list.iter().enumerate() {
+      let variant = data[i].unwrap();
+      for (neighbor, dir) in cell.neighbors.iter().filter(|(_, d)| *d == Dim2d::Right) {
+        let right = rules.rule_for(&variant).unwrap().socket_for(dir).unwrap();
+        let left = rules
+          .rule_for(&data[*neighbor].unwrap())
+          .unwrap()
+          .socket_for(&Dim2d::Left)
+          .unwrap();
+
+        assert_eq!(right.chars().rev().collect::<String>(), *left);
+      }
+    }
+  }
+
+  #[test]
+  fn matrix_pairs_and_wildcards() {
+    let matrix = MatrixConstraint::default()
+      .with_symmetric_pair("plug", "socket")
+      .with_pair("water-left", "water-right")
+      .with_wildcard("any");
+
+    assert!(matrix.check(&"plug", &hashset! {"socket"}));
+    assert!(matrix.check(&"socket", &hashset! {"plug", "water-left"}));
+    assert!(!matrix.check(&"plug", &hashset! {"plug"}));
+
+    assert!(matrix.check(&"water-left", &hashset! {"water-right"}));
+    assert!(!matrix.check(&"water-right", &hashset! {"water-left"}));
+
+    assert!(matrix.check(&"plug", &hashset! {"any"}));
+    assert!(matrix.check(&"any", &hashset! {"water-right"}));
+    assert!(!matrix.check(&"any", &hashset! {}));
+  }
+
+  #[test]
+  fn complementary_sockets_alternate() {
+    let rules: Rules<char, Dim2d, &str> = RuleBuilder::default()
+      .with_rule('p', Rule::splat("plug"))
+      .with_rule('s', Rule::splat("socket"))
+      .into();
+
+    let matrix = MatrixConstraint::default().with_symmetric_pair("plug", "socket");
+
+    let mut state = StateBuilder::new([6, 6], RandomObserver::new(Some(SEED)), matrix, rules)
+      .build()
+      .unwrap();
+
+    crate::collapse(&mut state).unwrap();
+
+    let data = state.data_raw();
+
+    for (i, cell) in state.cells().list.iter().enumerate() {
+      for (neighbor, _) in &cell.neighbors {
+        assert_ne!(data[i], data[*neighbor]);
+      }
+    }
+  }
+
+  #[test]
+  fn inferred_sockets_reproduce_adjacency() {
+    let table = AdjacencyTable::default()
+      .with('~', Dim2d::Right, '~')
+      .with('~', Dim2d::Right, '.')
+      .with('.', Dim2d::Right, '.')
+      .with('.', Dim2d::Right, '^')
+      .with('^', Dim2d::Right, '^')
+      .with('w', Dim2d::Right, '.')
+      .with('w', Dim2d::Right, '~')
+      .with('~', Dim2d::Up, '~')
+      .with('~', Dim2d::Up, 'w')
+      .with('.', Dim2d::Up, '.')
+      .with('.', Dim2d::Up, '^')
+      .with('^', Dim2d::Up, '^')
+      .with('w', Dim2d::Up, 'w');
+
+    let rules = table.infer_sockets();
+    let variants = ['~', '.', '^', 'w'];
+
+    for variant in variants {
+      for dir in Dim2d::iter() {
+        for neighbor in variants {
+          let socket = rules.rule_for(&variant).unwrap().socket_for(&dir).unwrap();
+          let facing = rules
+            .rule_for(&neighbor)
+            .unwrap()
+            .socket_for(&dir.opposite())
+            .unwrap();
+
+          assert_eq!(
+            SetConstraint.check(socket, &HashSet::from([facing.clone()])),
+            table.allows(&variant, dir, &neighbor),
+            "{variant:?} {dir:?} {neighbor:?}"
+          );
+        }
+      }
+    }
+
+    // '~' and 'w' have the same neighbors to the right, so they share a socket
+    assert_eq!(
+      rules.rule_for(&'~').unwrap().socket_for(&Dim2d::Right),
+      rules.rule_for(&'w').unwrap().socket_for(&Dim2d::Right)
+    );
+  }
+}
@@ -4,7 +4,7 @@
 
 pub mod bevy;
 
-use crate::Dimension;
+use crate::{Dimension, Planar};
 use strum_macros::{EnumCount, EnumIter, VariantArray};
 
 #[derive(
@@ -49,6 +49,25 @@ impl Dimension for Dim2d {
   }
 }
 
+impl Planar for Dim2d {
+  fn rotated(&self) -> Self {
+    match self {
+      Self::Up => Self::Right,
+      Self::Right => Self::Down,
+      Self::Down => Self::Left,
+      Self::Left => Self::Up,
+    }
+  }
+
+  fn reflected(&self) -> Self {
+    match self {
+      Self::Left => Self::Right,
+      Self::Right => Self::Left,
+      other => *other,
+    }
+  }
+}
+
 #[derive(
   PartialEq, Eq, Hash, PartialOrd, Ord, EnumCount, EnumIter, VariantArray, Clone, Copy, Debug,
 )]
@@ -1,4 +1,4 @@
-use crate::Dimension;
+use crate::{Dimension, Planar};
 use strum_macros::{EnumCount, EnumIter, VariantArray};
 
 /// Bevy specific version of 2d that is to be used where Up is Y+
@@ -25,6 +25,25 @@ impl Dimension for Dim2d {
   }
 }
 
+impl Planar for Dim2d {
+  fn rotated(&self) -> Self {
+    match self {
+      Self::YPos => Self::XPos,
+      Self::XPos => Self::YNeg,
+      Self::YNeg => Self::XNeg,
+      Self::XNeg => Self::YPos,
+    }
+  }
+
+  fn reflected(&self) -> Self {
+    match self {
+      Self::XNeg => Self::XPos,
+      Self::XPos => Self::XNeg,
+      other => *other,
+    }
+  }
+}
+
 /// Bevy specific version of 3d that is to be used where Up is Y+
 #[derive(
   PartialEq, Eq, Hash, PartialOrd, Ord, EnumCount, EnumIter, VariantArray, Clone, Copy, Debug,
@@ -0,0 +1,313 @@
+//! Coarse to fine generation, where a low resolution output decides what each block of a high resolution output may contain
+
+use crate::{
+  Dimension, DirectionalConstraint, Error, Observer, Rules, Size, Socket, StateBuilder, UPos,
+  Variant, err, prebuilt::regions::Mask,
+};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Maps every coarse cell onto a block of fine cells, restricting the block to the fine variants allowed by the coarse variant.
+///
+/// All blocks are solved within a single fine state, so propagation keeps the borders between adjacent blocks consistent.
+/// Coarse variants without a rule do not restrict their block
+#[derive(Debug)]
+pub struct Refinement<CV: Variant, FV: Variant, const DIM: usize> {
+  scale: Size<DIM>,
+  allowed: HashMap<CV, BTreeSet<FV>>,
+  blend: bool,
+}
+
+impl<CV: Variant, FV: Variant, const DIM: usize> Clone for Refinement<CV, FV, DIM> {
+  fn clone(&self) -> Self {
+    Self {
+      scale: self.scale,
+      allowed: self.allowed.clone(),
+      blend: self.blend,
+    }
+  }
+}
+
+impl<CV: Variant, FV: Variant, const DIM: usize> Refinement<CV, FV, DIM> {
+  /// Scale is the size of the block of fine cells each coarse cell maps onto
+  ///
+  /// # Panics
+  ///
+  /// If the scale is zero along any axis
+  pub fn new(scale: impl Into<Size<DIM>>) -> Self {
+    let scale = scale.into();
+    assert!(
+      !scale.is_empty(),
+      "Scale must be at least 1 along every axis"
+    );
+
+    Self {
+      scale,
+      allowed: HashMap::new(),
+      blend: false,
+    }
+  }
+
+  /// Allows the fine variants within blocks of the coarse variant, in addition to any previously allowed
+  pub fn add_rule(&mut self, coarse: CV, allowed: impl IntoIterator<Item = FV>) -> &mut Self {
+    self.allowed.entry(coarse).or_default().extend(allowed);
+    self
+  }
+
+  pub fn with_rule(mut self, coarse: CV, allowed: impl IntoIterator<Item = FV>) -> Self {
+    self.add_rule(coarse, allowed);
+    self
+  }
+
+  /// When blending, the fine cells along the edge of a block may also use the variants allowed by the neighboring block,
+  /// giving room for transitions between blocks
+  pub fn with_blending(mut self, blend: bool) -> Self {
+    self.blend = blend;
+    self
+  }
+
+  pub fn fine_size(&self, coarse_size: Size<DIM>) -> Size<DIM> {
+    Size::new(std::array::from_fn(|i| coarse_size[i] * self.scale[i]))
+  }
+
+  pub fn coarse_position(&self, fine: &UPos<DIM>) -> UPos<DIM> {
+    UPos(fine.component_div(&self.scale))
+  }
+
+  /// The fine variants allowed at the fine position, None if unrestricted.
+  /// The coarse output must have one variant for every cell of the coarse size
+  pub fn allowed_at(
+    &self,
+    coarse: &[CV],
+    coarse_size: Size<DIM>,
+    fine: &UPos<DIM>,
+  ) -> Result<Option<BTreeSet<FV>>, err::Error<DIM>> {
+    if coarse.len() != coarse_size.len() {
+      return Err(Error::LayerSizeMismatch {
+        expected: coarse_size.len(),
+        found: coarse.len(),
+      });
+    }
+
+    Ok(self.allowed_within(coarse, coarse_size, fine))
+  }
+
+  fn allowed_within(
+    &self,
+    coarse: &[CV],
+    coarse_size: Size<DIM>,
+    fine: &UPos<DIM>,
+  ) -> Option<BTreeSet<FV>> {
+    let block = self.coarse_position(fine);
+    let mut allowed = self.allowed.get(&coarse[block.index(coarse_size)])?.clone();
+
+    if !self.blend {
+      return Some(allowed);
+    }
+
+    for axis in 0..DIM {
+      let offset = fine[axis] % self.scale[axis];
+
+      let neighbor = if offset == 0 && block[axis] > 0 {
+        Some(block[axis] - 1)
+      } else if offset == self.scale[axis] - 1 && block[axis] + 1 < coarse_size[axis] {
+        Some(block[axis] + 1)
+      } else {
+        None
+      };
+
+      if let Some(neighbor) = neighbor {
+        let mut neighbor_block = block;
+        neighbor_block[axis] = neighbor;
+        allowed.extend(
+          self
+            .allowed
+            .get(&coarse[neighbor_block.index(coarse_size)])?
+            .iter()
+            .cloned(),
+        );
+      }
+    }
+
+    Some(allowed)
+  }
+
+  /// Restricts every cell of the fine builder to the variants allowed by its coarse cell.
+  /// The coarse output must have one variant for every coarse cell, and the size of the builder must be the fine size
+  pub fn apply<'b, O, C, D, S>(
+    &self,
+    coarse: &[CV],
+    coarse_size: impl Into<Size<DIM>>,
+    builder: &'b mut StateBuilder<O, C, FV, D, S, DIM>,
+  ) -> Result<&'b mut StateBuilder<O, C, FV, D, S, DIM>, err::Error<DIM>>
+  where
+    O: Observer<FV>,
+    C: DirectionalConstraint<FV, D, S>,
+    D: Dimension,
+    S: Socket,
+  {
+    let coarse_size = coarse_size.into();
+    let size = *builder.size();
+
+    if coarse.len() != coarse_size.len() {
+      return Err(Error::LayerSizeMismatch {
+        expected: coarse_size.len(),
+        found: coarse.len(),
+      });
+    }
+
+    let fine_size = self.fine_size(coarse_size);
+    if *size != *fine_size {
+      return Err(Error::LayerSizeMismatch {
+        expected: fine_size.len(),
+        found: size.len(),
+      });
+    }
+
+    // group cells with identical restrictions so each set is only applied once
+    let mut groups = BTreeMap::<BTreeSet<FV>, Vec<bool>>::new();
+    for i in 0..size.len() {
+      let fine = UPos::from_index(i, size);
+      if let Some(allowed) = self.allowed_within(coarse, coarse_size, &fine) {
+        groups
+          .entry(allowed)
+          .or_insert_with(|| vec![false; size.len()])[i] = true;
+      }
+    }
+
+    for (allowed, mask) in groups {
+      builder.restrict(Mask::new(size, mask), allowed);
+    }
+
+    Ok(builder)
+  }
+
+  /// Creates a fine builder from a coarse output
+  pub fn builder<O, C, D, S>(
+    &self,
+    coarse: &[CV],
+    coarse_size: impl Into<Size<DIM>>,
+    obs: O,
+    constraint: C,
+    rules: impl Into<Rules<FV, D, S>>,
+  ) -> Result<StateBuilder<O, C, FV, D, S, DIM>, err::Error<DIM>>
+  where
+    O: Observer<FV>,
+    C: DirectionalConstraint<FV, D, S>,
+    D: Dimension,
+    S: Socket,
+  {
+    let coarse_size = coarse_size.into();
+    let mut builder = StateBuilder::new(self.fine_size(coarse_size), obs, constraint, rules);
+    self.apply(coarse, coarse_size, &mut builder)?;
+    Ok(builder)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::Refinement;
+  use crate::{
+    Error, Rule, RuleBuilder, Rules, StateBuilder, UPos,
+    prebuilt::{
+      Dim2d,
+      constraints::{SetConstraint, UnaryConstraint},
+      processing::RandomObserver,
+    },
+  };
+  use std::collections::BTreeSet;
+
+  const SEED: u64 = 123;
+
+  #[test]
+  fn fine_cells_follow_coarse_cells() {
+    let coarse_rules: Rules<char, Dim2d, u8> = RuleBuilder::default()
+      .with_rule('L', Rule::splat(0))
+      .with_rule('S', Rule::splat(0))
+      .into();
+
+    let fine_rules: Rules<char, Dim2d, BTreeSet<u8>> = RuleBuilder::default()
+      .with_rule('g', Rule::splat(BTreeSet::from([0])))
+      .with_rule('s', Rule::splat(BTreeSet::from([0, 1])))
+      .with_rule('w', Rule::splat(BTreeSet::from([1])))
+      .into();
+
+    let coarse_size = [4, 4];
+    let mut coarse = StateBuilder::new(
+      coarse_size,
+      RandomObserver::new(Some(SEED)),
+      UnaryConstraint,
+      coarse_rules,
+    )
+    .build()
+    .unwrap();
+
+    crate::collapse(&mut coarse).unwrap();
+
+    let coarse: Vec<_> = coarse.into();
+
+    let refinement = Refinement::new([4, 4])
+      .with_rule('L', ['g', 's'])
+      .with_rule('S', ['w', 's']);
+
+    let mut fine = refinement
+      .builder(
+        &coarse,
+        coarse_size,
+        RandomObserver::new(Some(SEED)),
+        SetConstraint,
+        fine_rules,
+      )
+      .unwrap()
+      .build()
+      .unwrap();
+
+    crate::collapse(&mut fine).unwrap();
+
+    let size = *fine.size();
+    let fine: Vec<_> = fine.into();
+
+    for (i, variant) in fine.iter().enumerate() {
+      let pos = UPos::from_index(i, size);
+      let allowed = refinement
+        .allowed_at(&coarse, coarse_size.into(), &pos)
+        .unwrap()
+        .unwrap();
+      assert!(allowed.contains(variant));
+    }
+  }
+
+  #[test]
+  fn sizes_are_checked() {
+    let rules: Rules<char, Dim2d, u8> =
+      RuleBuilder::default().with_rule('a', Rule::splat(0)).into();
+    let refinement = Refinement::new([2, 2]).with_rule('L', ['a']);
+
+    assert!(matches!(
+      refinement.allowed_at(&['L'; 3], [2, 2].into(), &UPos::new([0, 0])),
+      Err(Error::LayerSizeMismatch {
+        expected: 4,
+        found: 3
+      })
+    ));
+
+    let mut builder = StateBuilder::new(
+      [4, 2],
+      RandomObserver::new(Some(SEED)),
+      UnaryConstraint,
+      rules,
+    );
+    assert!(matches!(
+      refinement.apply(&['L'; 4], [2, 2], &mut builder),
+      Err(Error::LayerSizeMismatch {
+        expected: 16,
+        found: 8
+      })
+    ));
+  }
+
+  #[test]
+  #[should_panic]
+  fn zero_scales_are_rejected() {
+    Refinement::<char, char, 2>::new([2, 0]);
+  }
+}
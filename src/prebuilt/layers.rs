@@ -0,0 +1,175 @@
+//! Layers are generated one after another on grids of the same size,
+//! with the output of each layer restricting which variants the next layer may place in the same cell
+
+use crate::{
+  Dimension, DirectionalConstraint, Error, Observer, Socket, StateBuilder, Variant, err,
+  prebuilt::regions::Mask,
+};
+use std::collections::{BTreeSet, HashMap};
+
+/// Describes which variants of a layer may occupy the same cell as a variant of the layer below it.
+/// Lower variants without a rule do not restrict the upper layer
+#[derive(Debug)]
+pub struct LayerRules<L: Variant, U: Variant> {
+  allowed: HashMap<L, BTreeSet<U>>,
+}
+
+impl<L: Variant, U: Variant> Default for LayerRules<L, U> {
+  fn default() -> Self {
+    Self {
+      allowed: Default::default(),
+    }
+  }
+}
+
+impl<L: Variant, U: Variant> Clone for LayerRules<L, U> {
+  fn clone(&self) -> Self {
+    Self {
+      allowed: self.allowed.clone(),
+    }
+  }
+}
+
+impl<L: Variant, U: Variant> LayerRules<L, U> {
+  /// Allows the upper variants on top of the lower variant, in addition to any previously allowed
+  pub fn add_rule(&mut self, lower: L, allowed: impl IntoIterator<Item = U>) -> &mut Self {
+    self.allowed.entry(lower).or_default().extend(allowed);
+    self
+  }
+
+  pub fn with_rule(mut self, lower: L, allowed: impl IntoIterator<Item = U>) -> Self {
+    self.add_rule(lower, allowed);
+    self
+  }
+
+  pub fn allows(&self, lower: &L, upper: &U) -> bool {
+    self
+      .allowed
+      .get(lower)
+      .is_none_or(|allowed| allowed.contains(upper))
+  }
+
+  /// Restricts every cell of the upper layer's builder to the variants allowed on top of the lower layer's output,
+  /// which must have one variant for every cell of the builder
+  pub fn apply<'b, O, C, D, S, const DIM: usize>(
+    &self,
+    lower: &[L],
+    builder: &'b mut StateBuilder<O, C, U, D, S, DIM>,
+  ) -> Result<&'b mut StateBuilder<O, C, U, D, S, DIM>, err::Error<DIM>>
+  where
+    O: Observer<U>,
+    C: DirectionalConstraint<U, D, S>,
+    D: Dimension,
+    S: Socket,
+  {
+    let size = *builder.size();
+
+    if lower.len() != size.len() {
+      return Err(Error::LayerSizeMismatch {
+        expected: size.len(),
+        found: lower.len(),
+      });
+    }
+
+    for (variant, allowed) in &self.allowed {
+      let mask = lower.iter().map(|l| l == variant).collect::<Vec<_>>();
+      builder.restrict(Mask::new(size, mask), allowed.iter().cloned());
+    }
+
+    Ok(builder)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::LayerRules;
+  use crate::{
+    Error, Rule, RuleBuilder, Rules, StateBuilder,
+    prebuilt::{Dim2d, constraints::UnaryConstraint, processing::RandomObserver},
+  };
+
+  const SEED: u64 = 123;
+
+  #[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Clone)]
+  enum Ground {
+    Water,
+    Grass,
+    Wall,
+  }
+
+  #[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Clone)]
+  enum Object {
+    Nothing,
+    Tree,
+    Door,
+  }
+
+  #[test]
+  fn lower_layers_restrict_upper_layers() {
+    let ground_rules: Rules<Ground, Dim2d, u8> = RuleBuilder::default()
+      .with_rule(Ground::Water, Rule::splat(0))
+      .with_rule(Ground::Grass, Rule::splat(0))
+      .with_rule(Ground::Wall, Rule::splat(0))
+      .into();
+
+    let object_rules: Rules<Object, Dim2d, u8> = RuleBuilder::default()
+      .with_rule(Object::Nothing, Rule::splat(0))
+      .with_rule(Object::Tree, Rule::splat(0))
+      .with_rule(Object::Door, Rule::splat(0))
+      .into();
+
+    let layer_rules = LayerRules::default()
+      .with_rule(Ground::Water, [Object::Nothing])
+      .with_rule(Ground::Grass, [Object::Nothing, Object::Tree])
+      .with_rule(Ground::Wall, [Object::Nothing, Object::Door]);
+
+    let mut ground = StateBuilder::new(
+      [10, 10],
+      RandomObserver::new(Some(SEED)),
+      UnaryConstraint,
+      ground_rules,
+    )
+    .build()
+    .unwrap();
+
+    crate::collapse(&mut ground).unwrap();
+
+    let ground: Vec<_> = ground.into();
+
+    let mut builder = StateBuilder::new(
+      [10, 10],
+      RandomObserver::new(Some(SEED)),
+      UnaryConstraint,
+      object_rules,
+    );
+
+    layer_rules.apply(&ground, &mut builder).unwrap();
+
+    let mut objects = builder.build().unwrap();
+
+    crate::collapse(&mut objects).unwrap();
+
+    let objects: Vec<_> = objects.into();
+
+    assert!(
+      ground
+        .iter()
+        .zip(objects.iter())
+        .all(|(g, o)| layer_rules.allows(g, o))
+    );
+
+    let mut smaller = StateBuilder::new(
+      [5, 10],
+      RandomObserver::new(Some(SEED)),
+      UnaryConstraint,
+      RuleBuilder::<Object, Dim2d, u8>::default(),
+    );
+    assert!(matches!(
+      layer_rules.apply(&ground, &mut smaller),
+      Err(Error::LayerSizeMismatch {
+        expected: 50,
+        found: 100
+      })
+    ));
+  }
+}
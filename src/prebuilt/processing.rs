@@ -1,11 +1,17 @@
-use crate::{CellIndex, Dimension, Error, Modifier, Observer, Shape, Variant, cells::Cells, err};
+use crate::{
+  CellIndex, Dimension, Error, Metric, Modifier, Observer, Shape, Variant, cells::Cells, err,
+};
 use derive_more::derive::{Deref, DerefMut};
 use rand::{
   RngCore, SeedableRng,
   seq::{IndexedRandom, IteratorRandom},
 };
 use rand_chacha::ChaCha20Rng;
-use std::{collections::HashMap, iter::Iterator, marker::PhantomData};
+use std::{
+  collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+  iter::Iterator,
+  marker::PhantomData,
+};
 
 /// Randomly selects from a set of variants for collapsing
 #[derive(Debug)]
@@ -82,8 +88,10 @@ impl<V: Variant> Modifier<V> for RandomObserver {
   fn modify<D: Dimension, const DIM: usize>(
     &mut self,
     _variant: &V,
+    _index: CellIndex,
     _cells: &mut Cells<V, D, DIM>,
-  ) {
+  ) -> Result<(), err::Error<DIM>> {
+    Ok(())
   }
 
   fn chain<C>(self, other: C) -> Self::Chained<C>
@@ -185,8 +193,10 @@ impl<S: Shape> Modifier<S::Variant> for WeightedObserver<S> {
   fn modify<D: Dimension, const DIM: usize>(
     &mut self,
     _variant: &S::Variant,
+    _index: CellIndex,
     _cells: &mut Cells<S::Variant, D, DIM>,
-  ) {
+  ) -> Result<(), err::Error<DIM>> {
+    Ok(())
   }
 
   fn chain<C>(self, other: C) -> Self::Chained<C>
@@ -198,7 +208,8 @@ impl<S: Shape> Modifier<S::Variant> for WeightedObserver<S> {
 }
 
 /// Applies limits to variant selection.
-/// Only stops the wave function from selecting any more than the amount, does not enforce that number to be reached
+/// Only stops the wave function from selecting any more than the amount, does not enforce that number to be reached.
+/// See [`CountMod`] for minimum and exact counts
 #[derive(Debug, Deref, DerefMut)]
 pub struct LimitMod<V: Variant>(HashMap<V, usize>);
 
@@ -218,27 +229,502 @@ impl<V: Variant> Modifier<V> for LimitMod<V> {
   type Chained<C: Modifier<V>> = (Self, C);
 
   #[profiling::function]
-  fn modify<D: Dimension, const DIM: usize>(&mut self, variant: &V, cells: &mut Cells<V, D, DIM>) {
+  fn modify<D: Dimension, const DIM: usize>(
+    &mut self,
+    variant: &V,
+    _index: CellIndex,
+    cells: &mut Cells<V, D, DIM>,
+  ) -> Result<(), err::Error<DIM>> {
     let Some(limit) = self.get_mut(variant) else {
-      return;
+      return Ok(());
     };
 
     *limit = limit.saturating_sub(1);
 
     if *limit > 0 {
+      return Ok(());
+    }
+
+    for i in cells.uncollapsed_indexes().collect::<Vec<_>>() {
+      cells.remove_variant(i, variant)?;
+    }
+
+    Ok(())
+  }
+
+  fn chain<C>(self, other: C) -> Self::Chained<C>
+  where
+    C: Modifier<V>,
+  {
+    (self, other)
+  }
+}
+
+/// Bounds on the number of times a variant may be selected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Count {
+  pub min: usize,
+  pub max: Option<usize>,
+}
+
+impl Count {
+  pub fn exact(count: usize) -> Self {
+    Self {
+      min: count,
+      max: Some(count),
+    }
+  }
+
+  pub fn at_least(min: usize) -> Self {
+    Self { min, max: None }
+  }
+
+  pub fn at_most(max: usize) -> Self {
+    Self {
+      min: 0,
+      max: Some(max),
+    }
+  }
+
+  /// # Panics
+  ///
+  /// If `min` is greater than `max`
+  pub fn between(min: usize, max: usize) -> Self {
+    let count = Self {
+      min,
+      max: Some(max),
+    };
+    count.validate();
+    count
+  }
+
+  fn validate(&self) {
+    if let Some(max) = self.max {
+      assert!(
+        self.min <= max,
+        "Count minimum of {} is greater than its maximum of {max}",
+        self.min
+      );
+    }
+  }
+}
+
+/// Enforces minimum, maximum, and exact counts of variants.
+///
+/// Once a maximum is reached the variant is removed from all remaining cells.
+/// Once the remaining cells that can hold a variant are only just enough to reach its minimum,
+/// those cells are restricted to that variant. An error is reported as soon as a count becomes unreachable,
+/// checking variants in order
+#[derive(Debug)]
+pub struct CountMod<V: Variant> {
+  counts: BTreeMap<V, Count>,
+  selected: HashMap<V, usize>,
+  /// Uncollapsed cells that can still hold each counted variant
+  candidates: BTreeMap<V, BTreeSet<CellIndex>>,
+  /// Watcher of the cells that changed since the candidates were updated, `None` until the first modification
+  watcher: Option<usize>,
+}
+
+impl<V: Variant> Clone for CountMod<V> {
+  fn clone(&self) -> Self {
+    Self {
+      counts: self.counts.clone(),
+      selected: self.selected.clone(),
+      candidates: self.candidates.clone(),
+      // a clone must watch the cells on its own rather than take the changes of the original
+      watcher: None,
+    }
+  }
+}
+
+impl<V: Variant> CountMod<V> {
+  /// # Panics
+  ///
+  /// If any count has a minimum greater than its maximum
+  pub fn new(counts: impl IntoIterator<Item = (V, Count)>) -> Self {
+    let counts = BTreeMap::from_iter(counts);
+    counts.values().for_each(Count::validate);
+
+    Self {
+      counts,
+      selected: HashMap::new(),
+      candidates: BTreeMap::new(),
+      watcher: None,
+    }
+  }
+
+  /// The number of times the variant has been selected so far
+  pub fn selected(&self, variant: &V) -> usize {
+    self.selected.get(variant).cloned().unwrap_or_default()
+  }
+
+  fn enforce_max<D: Dimension, const DIM: usize>(
+    &self,
+    variant: &V,
+    cells: &mut Cells<V, D, DIM>,
+  ) -> Result<(), err::Error<DIM>> {
+    let Some(max) = self.counts.get(variant).and_then(|count| count.max) else {
+      return Ok(());
+    };
+
+    let selected = self.selected(variant);
+
+    if selected > max {
+      return Err(Error::CountExceeded {
+        variant: format!("{variant:?}"),
+        selected,
+        max,
+      });
+    }
+
+    if selected == max {
+      for i in self.candidates_of(variant) {
+        cells.remove_variant(i, variant)?;
+      }
+    }
+
+    Ok(())
+  }
+
+  fn enforce_min<D: Dimension, const DIM: usize>(
+    &mut self,
+    cells: &mut Cells<V, D, DIM>,
+  ) -> Result<(), err::Error<DIM>> {
+    let deficits = self
+      .counts
+      .iter()
+      .map(|(variant, count)| (variant.clone(), count.min.saturating_sub(self.selected(variant))))
+      .filter(|(_, deficit)| *deficit > 0)
+      .collect::<Vec<_>>();
+
+    let mut required = HashSet::new();
+    let mut total_deficit = 0;
+
+    for (variant, deficit) in deficits {
+      self.track(cells);
+      let available = self.candidates.get(&variant).map_or(0, BTreeSet::len);
+
+      if available < deficit {
+        return Err(Error::CountUnreachable {
+          variant: format!("{variant:?}"),
+          required: deficit,
+          available,
+        });
+      }
+
+      if available == deficit {
+        for i in self.candidates_of(&variant) {
+          cells.retain(i, |v| *v == variant)?;
+        }
+      }
+
+      required.insert(variant);
+      total_deficit += deficit;
+    }
+
+    let uncollapsed = cells.uncollapsed_count();
+
+    if total_deficit > uncollapsed {
+      return Err(Error::CountsUnreachable {
+        required: total_deficit,
+        available: uncollapsed,
+      });
+    }
+
+    if total_deficit > 0 && total_deficit == uncollapsed {
+      let uncollapsed = cells
+        .entropy_cache
+        .iter()
+        .flatten()
+        .copied()
+        .collect::<Vec<_>>();
+
+      for i in uncollapsed {
+        cells.retain(i, |v| required.contains(v))?;
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Brings the candidates up to date with the cells that changed since the last call.
+  /// Possibilities are only ever removed, so only the changed cells need to be checked
+  fn track<D: Dimension, const DIM: usize>(&mut self, cells: &mut Cells<V, D, DIM>) {
+    let Some(changes) = self.watcher.and_then(|watcher| cells.take_changes(watcher)) else {
+      self.candidates = self
+        .counts
+        .keys()
+        .map(|variant| (variant.clone(), BTreeSet::new()))
+        .collect();
+
+      for i in cells.uncollapsed_indexes() {
+        let possibilities = &cells.at(i).possibilities;
+        for (variant, candidates) in &mut self.candidates {
+          if possibilities.contains(variant) {
+            candidates.insert(i);
+          }
+        }
+      }
+
+      self.watcher = Some(cells.watch());
       return;
+    };
+
+    for i in changes {
+      let cell = cells.at(i);
+      for (variant, candidates) in &mut self.candidates {
+        if cell.collapsed() || !cell.possibilities.contains(variant) {
+          candidates.remove(&i);
+        }
+      }
     }
+  }
 
-    for (i, cell) in cells
-      .list
-      .iter_mut()
-      .enumerate()
-      .filter(|(_, cell)| !cell.collapsed())
-    {
-      let starting_entropy = cell.entropy;
-      cell.remove_variant(variant);
-      cells.entropy_cache.set(starting_entropy, i, cell.entropy);
+  fn candidates_of(&self, variant: &V) -> Vec<CellIndex> {
+    self
+      .candidates
+      .get(variant)
+      .map(|candidates| candidates.iter().copied().collect())
+      .unwrap_or_default()
+  }
+}
+
+impl<V: Variant> Modifier<V> for CountMod<V> {
+  type Chained<C: Modifier<V>> = (Self, C);
+
+  #[profiling::function]
+  fn modify<D: Dimension, const DIM: usize>(
+    &mut self,
+    variant: &V,
+    _index: CellIndex,
+    cells: &mut Cells<V, D, DIM>,
+  ) -> Result<(), err::Error<DIM>> {
+    *self.selected.entry(variant.clone()).or_default() += 1;
+
+    self.track(cells);
+    self.enforce_max(variant, cells)?;
+    self.enforce_min(cells)
+  }
+
+  fn chain<C>(self, other: C) -> Self::Chained<C>
+  where
+    C: Modifier<V>,
+  {
+    (self, other)
+  }
+}
+
+/// How a proximity rule treats its targets around a collapsed source
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Proximity {
+  /// None of the targets may appear within the radius of the source
+  Exclude,
+  /// At least one of the targets must appear within the radius of the source
+  Require,
+}
+
+/// A distance based relationship between a source variant and a set of target variants
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProximityRule<V: Variant> {
+  pub source: V,
+  pub targets: BTreeSet<V>,
+  pub radius: f64,
+  pub metric: Metric,
+  pub kind: Proximity,
+}
+
+impl<V: Variant> Clone for ProximityRule<V> {
+  fn clone(&self) -> Self {
+    Self {
+      source: self.source.clone(),
+      targets: self.targets.clone(),
+      radius: self.radius,
+      metric: self.metric,
+      kind: self.kind,
+    }
+  }
+}
+
+impl<V: Variant> ProximityRule<V> {
+  pub fn new(
+    kind: Proximity,
+    source: V,
+    targets: impl IntoIterator<Item = V>,
+    radius: f64,
+  ) -> Self {
+    Self {
+      source,
+      targets: targets.into_iter().collect(),
+      radius,
+      metric: Metric::default(),
+      kind,
+    }
+  }
+
+  /// Bans the targets from appearing within the radius of the source
+  pub fn exclude(source: V, targets: impl IntoIterator<Item = V>, radius: f64) -> Self {
+    Self::new(Proximity::Exclude, source, targets, radius)
+  }
+
+  /// Requires one of the targets to appear within the radius of the source
+  pub fn require(source: V, targets: impl IntoIterator<Item = V>, radius: f64) -> Self {
+    Self::new(Proximity::Require, source, targets, radius)
+  }
+
+  pub fn with_metric(mut self, metric: Metric) -> Self {
+    self.metric = metric;
+    self
+  }
+}
+
+/// Applies distance based exclusion and attraction between variants.
+///
+/// Exclusions are symmetric, collapsing either the source or a target removes the other from the surrounding cells.
+/// Requirements are tracked until a target is collapsed within range, and once a single candidate cell remains it is restricted to the targets
+#[derive(Debug)]
+pub struct ProximityMod<V: Variant> {
+  rules: Vec<ProximityRule<V>>,
+  pending: Vec<(CellIndex, usize)>,
+}
+
+impl<V: Variant> Default for ProximityMod<V> {
+  fn default() -> Self {
+    Self {
+      rules: Default::default(),
+      pending: Default::default(),
+    }
+  }
+}
+
+impl<V: Variant> Clone for ProximityMod<V> {
+  fn clone(&self) -> Self {
+    Self {
+      rules: self.rules.clone(),
+      pending: self.pending.clone(),
+    }
+  }
+}
+
+impl<V: Variant> ProximityMod<V> {
+  pub fn new(rules: impl IntoIterator<Item = ProximityRule<V>>) -> Self {
+    Self {
+      rules: rules.into_iter().collect(),
+      pending: Vec::new(),
+    }
+  }
+
+  pub fn add_rule(&mut self, rule: ProximityRule<V>) -> &mut Self {
+    self.rules.push(rule);
+    self
+  }
+
+  pub fn with_rule(mut self, rule: ProximityRule<V>) -> Self {
+    self.add_rule(rule);
+    self
+  }
+
+  fn exclude<D: Dimension, const DIM: usize>(
+    cells: &mut Cells<V, D, DIM>,
+    index: CellIndex,
+    rule: &ProximityRule<V>,
+    banned: &BTreeSet<V>,
+  ) -> Result<(), err::Error<DIM>> {
+    let position = cells.at(index).position;
+
+    for i in cells.indexes_within(&position, rule.radius, rule.metric) {
+      let neighbor = cells.at(i);
+      if neighbor
+        .selected_variant()
+        .is_some_and(|v| banned.contains(v))
+      {
+        return Err(Error::ProximityViolated {
+          position,
+          neighbor: neighbor.position,
+        });
+      }
+
+      cells.retain(i, |v| !banned.contains(v))?;
+    }
+
+    Ok(())
+  }
+
+  /// Returns true once the requirement is satisfied
+  fn require<D: Dimension, const DIM: usize>(
+    cells: &mut Cells<V, D, DIM>,
+    index: CellIndex,
+    rule: &ProximityRule<V>,
+  ) -> Result<bool, err::Error<DIM>> {
+    let position = cells.at(index).position;
+    let mut candidates = Vec::new();
+
+    for i in cells.indexes_within(&position, rule.radius, rule.metric) {
+      let neighbor = cells.at(i);
+      match neighbor.selected_variant() {
+        Some(v) if rule.targets.contains(v) => return Ok(true),
+        Some(_) => {}
+        None => {
+          if !neighbor.possibilities.is_disjoint(&rule.targets) {
+            candidates.push(i);
+          }
+        }
+      }
+    }
+
+    match candidates.as_slice() {
+      [] => Err(Error::ProximityUnreachable { position }),
+      [i] => {
+        cells.retain(*i, |v| rule.targets.contains(v))?;
+        Ok(false)
+      }
+      _ => Ok(false),
+    }
+  }
+}
+
+impl<V: Variant> Modifier<V> for ProximityMod<V> {
+  type Chained<C: Modifier<V>> = (Self, C);
+
+  #[profiling::function]
+  fn modify<D: Dimension, const DIM: usize>(
+    &mut self,
+    variant: &V,
+    index: CellIndex,
+    cells: &mut Cells<V, D, DIM>,
+  ) -> Result<(), err::Error<DIM>> {
+    for (r, rule) in self.rules.iter().enumerate() {
+      match rule.kind {
+        Proximity::Exclude => {
+          if rule.source == *variant {
+            Self::exclude(cells, index, rule, &rule.targets)?;
+          }
+
+          if rule.targets.contains(variant) {
+            Self::exclude(cells, index, rule, &BTreeSet::from([rule.source.clone()]))?;
+          }
+        }
+        Proximity::Require => {
+          if rule.source == *variant {
+            self.pending.push((index, r));
+          }
+        }
+      }
+    }
+
+    let mut p = 0;
+    while p < self.pending.len() {
+      let (i, r) = self.pending[p];
+      if Self::require(cells, i, &self.rules[r])? {
+        self.pending.swap_remove(p);
+      } else {
+        p += 1;
+      }
     }
+
+    Ok(())
   }
 
   fn chain<C>(self, other: C) -> Self::Chained<C>
@@ -313,8 +799,13 @@ where
 {
   type Chained<C: Modifier<V>> = Chain<V, A, (Adj, C)>;
 
-  fn modify<D: Dimension, const DIM: usize>(&mut self, variant: &V, cells: &mut Cells<V, D, DIM>) {
-    self.adjuster.modify(variant, cells);
+  fn modify<D: Dimension, const DIM: usize>(
+    &mut self,
+    variant: &V,
+    index: CellIndex,
+    cells: &mut Cells<V, D, DIM>,
+  ) -> Result<(), err::Error<DIM>> {
+    self.adjuster.modify(variant, index, cells)
   }
 
   fn chain<C>(self, other: C) -> Self::Chained<C>
@@ -333,9 +824,14 @@ where
 {
   type Chained<C: Modifier<V>> = ((A0, A1), C);
 
-  fn modify<D: Dimension, const DIM: usize>(&mut self, variant: &V, cells: &mut Cells<V, D, DIM>) {
-    self.0.modify(variant, cells);
-    self.1.modify(variant, cells);
+  fn modify<D: Dimension, const DIM: usize>(
+    &mut self,
+    variant: &V,
+    index: CellIndex,
+    cells: &mut Cells<V, D, DIM>,
+  ) -> Result<(), err::Error<DIM>> {
+    self.0.modify(variant, index, cells)?;
+    self.1.modify(variant, index, cells)
   }
 
   fn chain<C>(self, other: C) -> Self::Chained<C>
@@ -345,3 +841,165 @@ where
     (self, other)
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::{Count, CountMod, ProximityMod, ProximityRule, RandomObserver};
+  use crate::{
+    Error, IPos, Metric, Modifier, RuleBuilder, Rules, StateBuilder,
+    prebuilt::{Dim2d, constraints::UnaryConstraint},
+  };
+  use maplit::hashmap;
+
+  const SEED: u64 = 123;
+
+  fn rules() -> Rules<char, Dim2d, u8> {
+    RuleBuilder::default()
+      .with_rule('a', |_| 0)
+      .with_rule('b', |_| 0)
+      .with_rule('c', |_| 0)
+      .into()
+  }
+
+  #[test]
+  fn counts_are_reached() {
+    let obs = RandomObserver::new(Some(SEED)).chain(CountMod::new(hashmap! {
+      'a' => Count::exact(1),
+      'b' => Count::at_least(20),
+      'c' => Count::at_most(3),
+    }));
+
+    let mut state = StateBuilder::new([6, 6], obs, UnaryConstraint, rules())
+      .build()
+      .unwrap();
+
+    crate::collapse(&mut state).unwrap();
+
+    let data: Vec<_> = state.into();
+    let count = |c| data.iter().filter(|v| **v == c).count();
+
+    assert_eq!(count('a'), 1);
+    assert!(count('b') >= 20);
+    assert!(count('c') <= 3);
+  }
+
+  #[test]
+  fn unreachable_count_is_reported() {
+    let obs = RandomObserver::new(Some(SEED)).chain(CountMod::new(hashmap! {
+      'a' => Count::at_least(10),
+      'b' => Count::at_least(10),
+    }));
+
+    let mut state = StateBuilder::new([4, 4], obs, UnaryConstraint, rules())
+      .build()
+      .unwrap();
+
+    assert!(matches!(
+      crate::collapse(&mut state),
+      Err(Error::CountsUnreachable { .. })
+    ));
+  }
+
+  #[test]
+  fn unreachable_counts_are_reported_in_order() {
+    let obs = RandomObserver::new(Some(SEED)).chain(CountMod::new(hashmap! {
+      'b' => Count::at_least(20),
+      'a' => Count::at_least(20),
+    }));
+
+    let mut state = StateBuilder::new([4, 4], obs, UnaryConstraint, rules())
+      .build()
+      .unwrap();
+
+    assert!(matches!(
+      crate::collapse(&mut state),
+      Err(Error::CountUnreachable { variant, .. }) if variant == "'a'"
+    ));
+  }
+
+  #[test]
+  fn propagated_removals_are_counted() {
+    // 'a' only connects to itself, so collapsing anything else first removes it from every cell
+    let rules: Rules<char, Dim2d, u8> = RuleBuilder::default()
+      .with_rule('a', |_| 1)
+      .with_rule('b', |_| 0)
+      .with_rule('c', |_| 0)
+      .into();
+
+    for seed in 0..16 {
+      let obs = RandomObserver::new(Some(seed)).chain(CountMod::new([('a', Count::at_least(1))]));
+      let mut state = StateBuilder::new([4, 4], obs, UnaryConstraint, rules.clone())
+        .build()
+        .unwrap();
+
+      match crate::collapse(&mut state) {
+        Ok(_) => assert!(state.data().iter().all(|v| *v == 'a')),
+        Err(Error::CountUnreachable { available, .. }) => assert_eq!(available, 0),
+        Err(e) => panic!("{e}"),
+      }
+    }
+  }
+
+  #[test]
+  #[should_panic]
+  fn inverted_counts_are_rejected() {
+    Count::between(3, 2);
+  }
+
+  #[test]
+  fn exclusions_are_respected() {
+    const RADIUS: f64 = 3.0;
+
+    let obs = RandomObserver::new(Some(SEED)).chain(
+      ProximityMod::default()
+        .with_rule(ProximityRule::exclude('a', ['a'], RADIUS).with_metric(Metric::Chebyshev)),
+    );
+
+    let size = [12, 12];
+    let mut state = StateBuilder::new(size, obs, UnaryConstraint, rules())
+      .build()
+      .unwrap();
+
+    crate::collapse(&mut state).unwrap();
+
+    let size = *state.size();
+    let data: Vec<_> = state.into();
+    let positions = data
+      .iter()
+      .enumerate()
+      .filter(|(_, v)| **v == 'a')
+      .map(|(i, _)| IPos::from_index(i, size))
+      .collect::<Vec<_>>();
+
+    assert!(!positions.is_empty());
+
+    for a in &positions {
+      for b in positions.iter().filter(|b| *b != a) {
+        assert!(Metric::Chebyshev.distance(a, b) > RADIUS);
+      }
+    }
+  }
+
+  #[test]
+  fn requirements_are_respected() {
+    const RADIUS: f64 = 2.0;
+
+    let obs = RandomObserver::new(Some(SEED))
+      .chain(ProximityMod::default().with_rule(ProximityRule::require('a', ['c'], RADIUS)));
+
+    let mut builder = StateBuilder::new([10, 10], obs, UnaryConstraint, rules());
+    builder.insert([5, 5], 'a');
+
+    let mut state = builder.build().unwrap();
+
+    crate::collapse(&mut state).unwrap();
+
+    let size = *state.size();
+    let data: Vec<_> = state.into();
+    let center = IPos::new([5, 5]);
+
+    assert!(data.iter().enumerate().any(|(i, v)| {
+      *v == 'c' && Metric::Manhattan.distance(&center, &IPos::from_index(i, size)) <= RADIUS
+    }));
+  }
+}
@@ -0,0 +1,72 @@
+use crate::{Region, Size, UPos};
+use derive_new::new;
+
+impl<const DIM: usize> Region<DIM> for UPos<DIM> {
+  fn contains(&self, pos: &UPos<DIM>) -> bool {
+    self == pos
+  }
+}
+
+/// A box shaped region, min is inclusive and max is exclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Bounds<const DIM: usize> {
+  pub min: UPos<DIM>,
+  pub max: UPos<DIM>,
+}
+
+impl<const DIM: usize> Bounds<DIM> {
+  pub fn new(min: impl Into<UPos<DIM>>, max: impl Into<UPos<DIM>>) -> Self {
+    Self {
+      min: min.into(),
+      max: max.into(),
+    }
+  }
+}
+
+impl<const DIM: usize> Region<DIM> for Bounds<DIM> {
+  fn contains(&self, pos: &UPos<DIM>) -> bool {
+    (0..DIM).all(|i| pos[i] >= self.min[i] && pos[i] < self.max[i])
+  }
+}
+
+/// A region described by a flag for every cell of a grid
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mask<const DIM: usize> {
+  size: Size<DIM>,
+  mask: Vec<bool>,
+}
+
+impl<const DIM: usize> Mask<DIM> {
+  pub fn new(size: impl Into<Size<DIM>>, mask: impl Into<Vec<bool>>) -> Self {
+    Self {
+      size: size.into(),
+      mask: mask.into(),
+    }
+  }
+}
+
+impl<const DIM: usize> Region<DIM> for Mask<DIM> {
+  fn contains(&self, pos: &UPos<DIM>) -> bool {
+    (0..DIM).all(|i| pos[i] < self.size[i])
+      && self
+        .mask
+        .get(pos.index(self.size))
+        .cloned()
+        .unwrap_or_default()
+  }
+}
+
+/// A region described by a function over positions
+#[derive(new, Debug, Clone, Copy)]
+pub struct Predicate<F>(F);
+
+impl<F, const DIM: usize> Region<DIM> for Predicate<F>
+where
+  F: Fn(&UPos<DIM>) -> bool,
+{
+  fn contains(&self, pos: &UPos<DIM>) -> bool {
+    (self.0)(pos)
+  }
+}
@@ -1,6 +1,6 @@
 use crate::{
+  CellIndex, Dimension, DimensionId, IPos, Shape, Variant, Weight,
   cells::{Cell, Cells},
-  CellIndex, Dimension, IPos, Shape, Variant, Weight,
 };
 use derive_more::derive::{Deref, DerefMut};
 use derive_new::new;
@@ -175,3 +175,265 @@ where
     self.shape1.weight(variant, index, cells) + self.shape2.weight(variant, index, cells)
   }
 }
+
+/// A shape that weights variants by how often they appeared next to the collapsed neighbors of the cell.
+///
+/// Cells without any collapsed neighbors use the base weights,
+/// otherwise the weights of every collapsed neighbor are summed together along with the smoothing value
+#[derive(Debug)]
+pub struct NeighborShape<V: Variant, W: Weight> {
+  base: HashMap<V, W>,
+  neighbors: HashMap<(V, DimensionId, V), W>,
+  smoothing: W,
+}
+
+impl<V: Variant, W: Weight> Clone for NeighborShape<V, W> {
+  fn clone(&self) -> Self {
+    Self {
+      base: self.base.clone(),
+      neighbors: self.neighbors.clone(),
+      smoothing: self.smoothing,
+    }
+  }
+}
+
+impl<V: Variant, W: Weight> NeighborShape<V, W> {
+  /// Neighbor weights are keyed by the variant, the direction from the variant to the neighbor, and the neighbor
+  pub fn new(
+    base: impl Into<HashMap<V, W>>,
+    neighbors: impl Into<HashMap<(V, DimensionId, V), W>>,
+  ) -> Self {
+    Self {
+      base: base.into(),
+      neighbors: neighbors.into(),
+      smoothing: W::default(),
+    }
+  }
+
+  /// Weight added to every variant of a cell with collapsed neighbors,
+  /// keeps variants selectable when they never appeared next to the neighbors
+  pub fn with_smoothing(mut self, smoothing: W) -> Self {
+    self.smoothing = smoothing;
+    self
+  }
+}
+
+impl<V: Variant, W: Weight> Shape for NeighborShape<V, W> {
+  type Variant = V;
+  type Weight = W;
+
+  #[profiling::function]
+  fn weight<D: Dimension, const DIM: usize>(
+    &self,
+    variant: &Self::Variant,
+    index: CellIndex,
+    cells: &Cells<Self::Variant, D, DIM>,
+  ) -> Self::Weight {
+    let mut collapsed = cells
+      .at(index)
+      .neighbors
+      .iter()
+      .filter_map(|(neighbor, dir)| {
+        cells
+          .at(*neighbor)
+          .selected_variant()
+          .map(|neighbor| (variant.clone(), DimensionId::of(dir), neighbor.clone()))
+      })
+      .peekable();
+
+    if collapsed.peek().is_none() {
+      return self.base.get(variant).cloned().unwrap_or_default();
+    }
+
+    collapsed.fold(self.smoothing, |weight, key| {
+      weight + self.neighbors.get(&key).cloned().unwrap_or_default()
+    })
+  }
+}
+
+/// A preference against a variant appearing next to a neighbor.
+/// Applies no matter which of the two is collapsed first
+#[derive(Debug)]
+pub struct SoftRule<V: Variant, W: Weight> {
+  pub variant: V,
+  pub neighbor: V,
+  /// Directions from the variant to the neighbor the rule applies to, every direction if empty
+  pub directions: Vec<DimensionId>,
+  /// Multiplier applied to the weight for every violation
+  pub factor: W,
+}
+
+impl<V: Variant, W: Weight> Clone for SoftRule<V, W> {
+  fn clone(&self) -> Self {
+    Self {
+      variant: self.variant.clone(),
+      neighbor: self.neighbor.clone(),
+      directions: self.directions.clone(),
+      factor: self.factor,
+    }
+  }
+}
+
+impl<V: Variant, W: Weight> SoftRule<V, W> {
+  pub fn new(variant: V, neighbor: V, factor: W) -> Self {
+    Self {
+      variant,
+      neighbor,
+      directions: Vec::new(),
+      factor,
+    }
+  }
+
+  /// Limits the rule to neighbors in the direction, may be called multiple times
+  pub fn with_direction<D: Dimension>(mut self, dir: D) -> Self {
+    self.directions.push(DimensionId::of(&dir));
+    self
+  }
+
+  fn violated_by(&self, variant: &V, dir: DimensionId, neighbor: &V) -> bool {
+    let applies = |dir| self.directions.is_empty() || self.directions.contains(&dir);
+
+    (self.variant == *variant && self.neighbor == *neighbor && applies(dir))
+      || (self.neighbor == *variant && self.variant == *neighbor && applies(dir.opposite()))
+  }
+}
+
+/// A shape that never removes possibilities, but penalizes candidates that violate soft rules against their collapsed neighbors.
+///
+/// The weight of the inner shape is multiplied by the factor of every violated rule,
+/// factors should be above zero so that every candidate remains selectable
+#[derive(Debug)]
+pub struct SoftShape<S: Shape> {
+  shape: S,
+  rules: Vec<SoftRule<S::Variant, S::Weight>>,
+}
+
+impl<S: Shape + Clone> Clone for SoftShape<S> {
+  fn clone(&self) -> Self {
+    Self {
+      shape: self.shape.clone(),
+      rules: self.rules.clone(),
+    }
+  }
+}
+
+impl<S: Shape> SoftShape<S> {
+  pub fn new(shape: S) -> Self {
+    Self {
+      shape,
+      rules: Vec::new(),
+    }
+  }
+
+  pub fn add_rule(&mut self, rule: SoftRule<S::Variant, S::Weight>) -> &mut Self {
+    self.rules.push(rule);
+    self
+  }
+
+  pub fn with_rule(mut self, rule: SoftRule<S::Variant, S::Weight>) -> Self {
+    self.add_rule(rule);
+    self
+  }
+
+  /// The factors of every rule the variant would violate if selected for the cell
+  pub fn violations<'s, D: Dimension, const DIM: usize>(
+    &'s self,
+    variant: &'s S::Variant,
+    index: CellIndex,
+    cells: &'s Cells<S::Variant, D, DIM>,
+  ) -> impl Iterator<Item = S::Weight> + 's {
+    cells
+      .at(index)
+      .neighbors
+      .iter()
+      .filter_map(|(neighbor, dir)| {
+        cells
+          .at(*neighbor)
+          .selected_variant()
+          .map(|neighbor| (neighbor, DimensionId::of(dir)))
+      })
+      .flat_map(move |(neighbor, dir)| {
+        self
+          .rules
+          .iter()
+          .filter(move |rule| rule.violated_by(variant, dir, neighbor))
+          .map(|rule| rule.factor)
+      })
+  }
+}
+
+impl<S: Shape> Shape for SoftShape<S> {
+  type Variant = S::Variant;
+  type Weight = S::Weight;
+
+  #[profiling::function]
+  fn weight<D: Dimension, const DIM: usize>(
+    &self,
+    variant: &Self::Variant,
+    index: CellIndex,
+    cells: &Cells<Self::Variant, D, DIM>,
+  ) -> Self::Weight {
+    self.violations(variant, index, cells).fold(
+      self.shape.weight(variant, index, cells),
+      |weight, factor| weight * factor,
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{SoftRule, SoftShape, WeightedShape};
+  use crate::{
+    Rule, RuleBuilder, Rules, Shape, StateBuilder,
+    prebuilt::{Dim2d, constraints::UnaryConstraint, processing::WeightedObserver},
+  };
+  use maplit::hashmap;
+
+  const SEED: u64 = 123;
+
+  fn touching<S: Shape<Variant = char>>(shape: S) -> usize {
+    let rules: Rules<char, Dim2d, u8> = RuleBuilder::default()
+      .with_rule('r', Rule::splat(0))
+      .with_rule('w', Rule::splat(0))
+      .with_rule('g', Rule::splat(0))
+      .into();
+
+    let mut state = StateBuilder::new(
+      [16, 16],
+      WeightedObserver::new(Some(SEED), shape),
+      UnaryConstraint,
+      rules,
+    )
+    .build()
+    .unwrap();
+
+    crate::collapse(&mut state).unwrap();
+
+    let data = state.data_raw();
+
+    state
+      .cells()
+      .list
+      .iter()
+      .enumerate()
+      .flat_map(|(i, cell)| cell.neighbors.iter().map(move |(n, _)| (i, *n)))
+      .filter(|(i, n)| data[*i] == Some('r') && data[*n] == Some('w'))
+      .count()
+  }
+
+  #[test]
+  fn soft_rules_reduce_violations() {
+    let weights = hashmap! {
+      'r' => 1.0,
+      'w' => 1.0,
+      'g' => 1.0,
+    };
+
+    let plain = touching(WeightedShape::new(weights.clone()));
+    let soft = touching(
+      SoftShape::new(WeightedShape::new(weights)).with_rule(SoftRule::new('r', 'w', 0.01)),
+    );
+
+    assert!(soft < plain, "{soft} < {plain}");
+  }
+}
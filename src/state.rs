@@ -1,5 +1,6 @@
 use crate::{
-  Constraint, Dimension, Error, Observation, Observer, Rules, Socket, Variant,
+  Connection, Dimension, DirectionalConstraint, Error, Observation, Observer, Region, Rules,
+  Socket, Stats, Variant,
   cells::{Cell, Cells},
   err,
   util::{self, Size, UPos},
@@ -8,6 +9,7 @@ use derive_more::derive::{Deref, DerefMut};
 use std::{
   collections::{BTreeSet, HashMap, HashSet},
   fmt::Debug,
+  time::Instant,
 };
 
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
@@ -15,7 +17,7 @@ use std::{
 pub struct StateBuilder<A, C, V, D, S, const DIM: usize>
 where
   A: Observer<V>,
-  C: Constraint<S>,
+  C: DirectionalConstraint<V, D, S>,
   V: Variant,
   D: Dimension,
   S: Socket,
@@ -26,13 +28,14 @@ where
   constraint: C,
   rules: Rules<V, D, S>,
   output_buffer: Vec<Option<V>>,
+  restrictions: Vec<Option<BTreeSet<V>>>,
   external_cells: ExtCells<V, D, DIM>,
 }
 
 impl<O, C, V, D, S, const DIM: usize> StateBuilder<O, C, V, D, S, DIM>
 where
   O: Observer<V>,
-  C: Constraint<S>,
+  C: DirectionalConstraint<V, D, S>,
   V: Variant,
   D: Dimension,
   S: Socket,
@@ -50,6 +53,7 @@ where
       constraint,
       rules: rules.into(),
       output_buffer: vec![None; size.len()],
+      restrictions: vec![None; size.len()],
       external_cells: ExtCells::new(size),
     }
   }
@@ -65,10 +69,63 @@ where
     self
   }
 
+  /// Inserts every cell from a previous output, in index order
+  pub fn fill(&mut self, output: impl IntoIterator<Item = V>) -> &mut Self {
+    for (cell, value) in self.output_buffer.iter_mut().zip(output) {
+      *cell = Some(value);
+    }
+    self
+  }
+
+  /// Removes any inserted cells within the region so they are regenerated.
+  /// The remaining inserted cells constrain the region so the seams stay valid
+  pub fn reopen(&mut self, region: impl Region<DIM>) -> &mut Self {
+    for (i, cell) in self.output_buffer.iter_mut().enumerate() {
+      if region.contains(&UPos::from_index(i, self.size)) {
+        *cell = None;
+      }
+    }
+    self
+  }
+
+  /// Restricts every cell within the region to the allowed variants
+  ///
+  /// Restricting the same cell multiple times keeps only the variants allowed by every restriction
+  pub fn restrict(
+    &mut self,
+    region: impl Region<DIM>,
+    allowed: impl IntoIterator<Item = V>,
+  ) -> &mut Self {
+    let allowed = BTreeSet::from_iter(allowed);
+
+    for (i, restriction) in self.restrictions.iter_mut().enumerate() {
+      if !region.contains(&UPos::from_index(i, self.size)) {
+        continue;
+      }
+
+      match restriction {
+        Some(current) => current.retain(|v| allowed.contains(v)),
+        None => *restriction = Some(allowed.clone()),
+      }
+    }
+
+    self
+  }
+
   pub fn size(&self) -> &Size<DIM> {
     &self.size
   }
 
+  pub fn rules(&self) -> &Rules<V, D, S> {
+    &self.rules
+  }
+
+  /// Replaces the rules, keeping any inserted cells and restrictions
+  pub fn set_rules(&mut self, rules: impl Into<Rules<V, D, S>>) -> &mut Self {
+    self.rules = rules.into();
+    self
+  }
+
   pub fn build(self) -> Result<State<O, C, V, D, S, DIM>, err::Error<DIM>> {
     // seemingly cannot be done at compile time because
     // M::Dimensions::COUNT is not accessible inside static asserts
@@ -85,6 +142,7 @@ where
       self.constraint,
       self.rules,
       self.output_buffer,
+      self.restrictions,
       self.external_cells,
     )
   }
@@ -93,7 +151,7 @@ where
 impl<A, C, V, D, S, const DIM: usize> Clone for StateBuilder<A, C, V, D, S, DIM>
 where
   A: Observer<V> + Clone,
-  C: Constraint<S> + Clone,
+  C: DirectionalConstraint<V, D, S> + Clone,
   V: Variant,
   D: Dimension,
   S: Socket,
@@ -104,6 +162,7 @@ where
       constraint: self.constraint.clone(),
       size: self.size,
       output_buffer: self.output_buffer.clone(),
+      restrictions: self.restrictions.clone(),
       rules: self.rules.clone(),
       external_cells: self.external_cells.clone(),
     }
@@ -116,7 +175,7 @@ where
 pub struct State<A, C, V, D, S, const DIM: usize>
 where
   A: Observer<V>,
-  C: Constraint<S>,
+  C: DirectionalConstraint<V, D, S>,
   V: Variant,
   D: Dimension,
   S: Socket,
@@ -126,12 +185,13 @@ where
   constraint: C,
   rules: Rules<V, D, S>,
   socket_cache: SocketCache<V, D, S>,
+  stats: Stats,
 }
 
 impl<O, C, V, D, S, const DIM: usize> State<O, C, V, D, S, DIM>
 where
   O: Observer<V>,
-  C: Constraint<S>,
+  C: DirectionalConstraint<V, D, S>,
   V: Variant,
   D: Dimension,
   S: Socket,
@@ -144,6 +204,7 @@ where
     constraint: C,
     rules: Rules<V, D, S>,
     input: Vec<Option<V>>,
+    restrictions: Vec<Option<BTreeSet<V>>>,
     external_cells: ExtCells<V, D, DIM>,
   ) -> Result<Self, err::Error<DIM>> {
     // create the state
@@ -153,10 +214,13 @@ where
       observer,
       constraint,
       socket_cache: Default::default(),
+      stats: Default::default(),
     };
 
     this.apply_external_information(external_cells)?;
 
+    this.apply_restrictions(restrictions)?;
+
     this.apply_predetermined_cells()?;
 
     Ok(this)
@@ -164,19 +228,52 @@ where
 
   #[profiling::function]
   pub fn collapse(&mut self) -> Result<Observation, err::Error<DIM>> {
+    let result = self.step();
+    match &result {
+      #[cfg(feature = "profiling")]
+      Ok(Observation::Complete) => crate::perf::collapse_event(&self.stats),
+      Ok(_) => {}
+      Err(_e) => {
+        self.stats.contradictions += 1;
+
+        #[cfg(feature = "profiling")]
+        crate::perf::contradiction_event(_e, &self.stats);
+      }
+    }
+    result
+  }
+
+  fn step(&mut self) -> Result<Observation, err::Error<DIM>> {
+    let start = Instant::now();
+
     let Some(index) = self.observer.observe(&mut self.cells)? else {
+      self.stats.observe_time += start.elapsed();
       return Ok(Observation::Complete);
     };
+    self.stats.observations += 1;
 
     let cell = &self.cells.list[index];
     let possibility = cell.selected_variant().cloned().unwrap();
 
-    self.observer.modify(&possibility, &mut self.cells);
-    self.propagate(index)?;
+    self.observer.modify(&possibility, index, &mut self.cells)?;
+
+    let observed = Instant::now();
+    self.stats.observe_time += observed - start;
+
+    let result = self
+      .propagate(index)
+      .and_then(|_| self.propagate_modified());
+    self.stats.propagate_time += observed.elapsed();
+    result?;
 
     Ok(Observation::Incomplete(index))
   }
 
+  /// Counters gathered while collapsing
+  pub fn stats(&self) -> &Stats {
+    &self.stats
+  }
+
   /// propagate the information of the supplied cell to its neighbors, and repeat until there are no more constraints made
   #[profiling::function]
   fn propagate(&mut self, cell_index: usize) -> Result<(), err::Error<DIM>> {
@@ -184,6 +281,7 @@ where
     stack.push(cell_index);
 
     while let Some(cell_index) = stack.pop() {
+      self.stats.propagation_steps += 1;
       let cell = &self.cells.at(cell_index);
 
       let neighbors = cell
@@ -205,6 +303,7 @@ where
           direction,
           &self.rules,
           &mut self.socket_cache,
+          &mut self.stats,
         )?;
         let new_entropy = neighbor.entropy;
 
@@ -214,6 +313,8 @@ where
             .cells
             .set_entropy(starting_entropy, neighbor_index, new_entropy);
           stack.push(neighbor_index);
+          self.stats.removed_possibilities += starting_entropy - new_entropy;
+          self.stats.max_stack_depth = self.stats.max_stack_depth.max(stack.len());
         }
       }
     }
@@ -221,6 +322,15 @@ where
     Ok(())
   }
 
+  /// propagate the cells that were reduced by modifiers
+  fn propagate_modified(&mut self) -> Result<(), err::Error<DIM>> {
+    while let Some(index) = self.cells.modified.pop() {
+      self.propagate(index)?;
+    }
+
+    Ok(())
+  }
+
   pub fn data(&self) -> Vec<V>
   where
     V: Default,
@@ -258,6 +368,38 @@ where
     &self.constraint
   }
 
+  /// Converts the state back into a builder, keeping every collapsed cell as an inserted cell.
+  /// External cells are not retained and must be supplied again if needed
+  pub fn into_builder<O2: Observer<V>>(self, observer: O2) -> StateBuilder<O2, C, V, D, S, DIM> {
+    let size = self.cells.size;
+    let output_buffer = self.data_raw();
+
+    StateBuilder {
+      size,
+      arbiter: observer,
+      constraint: self.constraint,
+      rules: self.rules,
+      output_buffer,
+      restrictions: vec![None; size.len()],
+      external_cells: ExtCells::new(size),
+    }
+  }
+
+  /// Reopens the region of this state and regenerates it with the observer, keeping everything else fixed
+  pub fn inpaint<O2: Observer<V>>(
+    self,
+    region: impl Region<DIM>,
+    observer: O2,
+  ) -> Result<State<O2, C, V, D, S, DIM>, err::Error<DIM>> {
+    let mut builder = self.into_builder(observer);
+    builder.reopen(region);
+
+    let mut state = builder.build()?;
+    crate::collapse(&mut state)?;
+
+    Ok(state)
+  }
+
   /// Tries to reduce the number of possibilities this tile can be based on a neighbor
   /// Returns true if at least one reduction was made, false otherwise
   #[profiling::function]
@@ -268,14 +410,24 @@ where
     neighbor_to_self_dir: D,
     rules: &Rules<V, D, S>,
     cache: &mut SocketCache<V, D, S>,
+    stats: &mut Stats,
   ) -> Result<(), err::Error<DIM>> {
     let neighbor_sockets = {
       profiling::function_scope!("Neighbor Sockets");
 
       match cache.lookup(neighbor_possibilities, &neighbor_to_self_dir) {
-        Some(Some(sockets)) => sockets,
-        Some(None) => cache.partial_create(rules, neighbor_possibilities, neighbor_to_self_dir),
-        None => cache.full_create(rules, neighbor_possibilities, neighbor_to_self_dir),
+        Some(Some(sockets)) => {
+          stats.cache_hits += 1;
+          sockets
+        }
+        Some(None) => {
+          stats.cache_misses += 1;
+          cache.partial_create(rules, neighbor_possibilities, neighbor_to_self_dir)
+        }
+        None => {
+          stats.cache_misses += 1;
+          cache.full_create(rules, neighbor_possibilities, neighbor_to_self_dir)
+        }
       }
     };
 
@@ -288,7 +440,15 @@ where
 
       self_rule
         .socket_for(&opposite)
-        .map(|socket| constraint.check(socket, neighbor_sockets))
+        .map(|socket| {
+          constraint.allows(&Connection::new(
+            variant,
+            socket,
+            opposite,
+            neighbor_possibilities,
+            neighbor_sockets,
+          ))
+        })
         .unwrap_or(false) // no rule means no connection
     });
 
@@ -325,6 +485,7 @@ where
           dir.opposite(),
           &self.rules,
           &mut self.socket_cache,
+          &mut self.stats,
         )?;
         let new_entropy = cell.entropy;
 
@@ -339,6 +500,32 @@ where
     Ok(())
   }
 
+  /// Reduces cells to their allowed variants, and propagate that information
+  fn apply_restrictions(
+    &mut self,
+    restrictions: Vec<Option<BTreeSet<V>>>,
+  ) -> Result<(), err::Error<DIM>> {
+    for (i, allowed) in restrictions.into_iter().enumerate() {
+      let Some(allowed) = allowed else {
+        continue;
+      };
+
+      let cell = self.cells.at(i);
+      if cell
+        .selected_variant()
+        .is_some_and(|variant| !allowed.contains(variant))
+      {
+        return Err(Error::Exhausted {
+          position: cell.position,
+        });
+      }
+
+      self.cells.retain(i, |variant| allowed.contains(variant))?;
+    }
+
+    self.propagate_modified()
+  }
+
   /// For any cells that are collapsed, propagate that information
   fn apply_predetermined_cells(&mut self) -> Result<(), err::Error<DIM>> {
     let propagations = self
@@ -350,8 +537,9 @@ where
       .collect::<Vec<_>>();
 
     for (i, variant) in propagations {
-      self.observer.modify(&variant, &mut self.cells);
+      self.observer.modify(&variant, i, &mut self.cells)?;
       self.propagate(i)?;
+      self.propagate_modified()?;
     }
 
     Ok(())
@@ -361,7 +549,7 @@ where
 impl<A, C, V, D, S, const DIM: usize> From<State<A, C, V, D, S, DIM>> for Vec<V>
 where
   A: Observer<V>,
-  C: Constraint<S>,
+  C: DirectionalConstraint<V, D, S>,
   V: Variant,
   D: Dimension,
   S: Socket,
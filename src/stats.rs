@@ -0,0 +1,110 @@
+use std::{ops::AddAssign, time::Duration};
+
+/// Counters gathered while collapsing a state
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "bevy", derive(bevy_reflect::Reflect))]
+pub struct Stats {
+  /// Cells selected by the observer
+  pub observations: usize,
+  /// Cells popped off the propagation stack
+  pub propagation_steps: usize,
+  /// Possibilities removed from cells by propagation
+  pub removed_possibilities: usize,
+  /// Neighbor sockets found in the socket cache
+  pub cache_hits: usize,
+  /// Neighbor sockets that had to be computed
+  pub cache_misses: usize,
+  /// The deepest the propagation stack has been
+  pub max_stack_depth: usize,
+  /// Time spent selecting cells and applying modifiers
+  pub observe_time: Duration,
+  /// Time spent propagating
+  pub propagate_time: Duration,
+  /// Collapse steps that failed
+  pub contradictions: usize,
+  /// Attempts made after a failed one, only counted by callers that retry
+  pub retries: usize,
+}
+
+impl Stats {
+  /// Folds in the stats of a failed attempt, counting it as a retry
+  pub fn add_retry(&mut self, failed: Stats) -> &mut Self {
+    *self += failed;
+    self.retries += 1;
+    self
+  }
+}
+
+impl AddAssign for Stats {
+  fn add_assign(&mut self, rhs: Self) {
+    self.observations += rhs.observations;
+    self.propagation_steps += rhs.propagation_steps;
+    self.removed_possibilities += rhs.removed_possibilities;
+    self.cache_hits += rhs.cache_hits;
+    self.cache_misses += rhs.cache_misses;
+    self.max_stack_depth = self.max_stack_depth.max(rhs.max_stack_depth);
+    self.observe_time += rhs.observe_time;
+    self.propagate_time += rhs.propagate_time;
+    self.contradictions += rhs.contradictions;
+    self.retries += rhs.retries;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::Stats;
+  use crate::{
+    RuleBuilder, Rules, StateBuilder,
+    prebuilt::{Dim2d, constraints::UnaryConstraint, processing::RandomObserver},
+  };
+  use std::time::Duration;
+
+  #[test]
+  fn collapsing_is_counted() {
+    let rules: Rules<u8, Dim2d, u8> = RuleBuilder::default()
+      .with_rule(0, |_| 0)
+      .with_rule(1, |_| 1)
+      .into();
+
+    let mut state = StateBuilder::new([4, 4], RandomObserver::new(Some(2)), UnaryConstraint, rules)
+      .build()
+      .unwrap();
+    let stats = crate::collapse(&mut state).unwrap();
+
+    assert_eq!(&stats, state.stats());
+    // the first observation decides every other cell, the rest are observed without removing anything
+    assert_eq!(stats.observations, 16);
+    assert_eq!(stats.removed_possibilities, 15);
+    assert_eq!(stats.propagation_steps, 16 + 15);
+    assert!(stats.cache_hits > 0);
+    assert!(stats.cache_misses > 0);
+    assert!(stats.max_stack_depth > 1);
+    assert_eq!(stats.contradictions, 0);
+    assert_eq!(stats.retries, 0);
+  }
+
+  #[test]
+  fn retries_are_accumulated() {
+    let failed = Stats {
+      observations: 3,
+      max_stack_depth: 8,
+      contradictions: 1,
+      propagate_time: Duration::from_millis(2),
+      ..Default::default()
+    };
+    let mut stats = Stats {
+      observations: 5,
+      max_stack_depth: 4,
+      propagate_time: Duration::from_millis(1),
+      ..Default::default()
+    };
+    stats.add_retry(failed);
+
+    assert_eq!(stats.observations, 8);
+    assert_eq!(stats.max_stack_depth, 8);
+    assert_eq!(stats.contradictions, 1);
+    assert_eq!(stats.propagate_time, Duration::from_millis(3));
+    assert_eq!(stats.retries, 1);
+  }
+}
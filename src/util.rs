@@ -1,5 +1,6 @@
 use crate::{DimensionId, err::ConversionError};
 use derive_more::derive::{Deref, DerefMut};
+use itertools::Itertools;
 use nalgebra::SVector;
 use std::{
   borrow::Borrow,
@@ -115,6 +116,16 @@ impl<const DIM: usize> IPos<DIM> {
   pub fn index_in(&self, size: Size<DIM>) -> usize {
     self.wrap(size).index(size)
   }
+
+  /// Iterates over all positions within the radius of this position, including itself
+  pub fn within(&self, radius: f64, metric: Metric) -> impl Iterator<Item = Self> + '_ {
+    let whole_num_range = radius as isize;
+    (0..DIM)
+      .map(|i| self[i] - whole_num_range..=self[i] + whole_num_range)
+      .multi_cartesian_product()
+      .map(|pos| Self(SVector::from_iterator(pos)))
+      .filter(move |pos| metric.distance(self, pos) <= radius)
+  }
 }
 
 impl<T, const DIM: usize> From<T> for IPos<DIM>
@@ -202,6 +213,31 @@ where
   }
 }
 
+/// Describes how the distance between two positions is measured
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "bevy", derive(bevy_reflect::Reflect))]
+pub enum Metric {
+  /// Sum of the distances along each axis
+  #[default]
+  Manhattan,
+  /// Largest distance along any axis
+  Chebyshev,
+  /// Straight line distance
+  Euclidean,
+}
+
+impl Metric {
+  pub fn distance<const DIM: usize>(&self, a: &IPos<DIM>, b: &IPos<DIM>) -> f64 {
+    let diff = (**a - **b).map(|i| i.abs());
+    match self {
+      Self::Manhattan => diff.sum() as f64,
+      Self::Chebyshev => diff.max() as f64,
+      Self::Euclidean => diff.map(|i| i as f64).norm(),
+    }
+  }
+}
+
 /// Converts an iterator of usize's that should match the length of DIM to a single dimensional index
 pub fn to_index<const DIM: usize>(
   iter: impl Iterator<Item = impl Borrow<usize>>,
@@ -267,7 +303,7 @@ where
 
 #[cfg(test)]
 mod tests {
-  use super::{IPos, Size, UPos};
+  use super::{IPos, Metric, Size, UPos};
 
   #[test]
   fn ipos_indexes() {
@@ -308,4 +344,17 @@ mod tests {
 
     assert_eq!(wrapped, IPos::new([0, 0]));
   }
+
+  #[test]
+  fn metric_distances() {
+    let a = IPos::new([0, 0]);
+    let b = IPos::new([3, -4]);
+
+    assert_eq!(Metric::Manhattan.distance(&a, &b), 7.0);
+    assert_eq!(Metric::Chebyshev.distance(&a, &b), 4.0);
+    assert_eq!(Metric::Euclidean.distance(&a, &b), 5.0);
+
+    assert_eq!(a.within(1.0, Metric::Manhattan).count(), 5);
+    assert_eq!(a.within(1.0, Metric::Chebyshev).count(), 9);
+  }
 }